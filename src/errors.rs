@@ -46,6 +46,13 @@ pub enum Error {
         context: &'static str,
     },
 
+    #[error("Cannot split a read into more than {max} reads\nwith read:\n{read}when {context}")]
+    TooManySplits {
+        max: usize,
+        read: Read,
+        context: &'static str,
+    },
+
     #[error("Error parsing patterns:\n\"{patterns}\"\n{source}")]
     ParsePatterns {
         patterns: String,
@@ -82,5 +89,5 @@ impl fmt::Display for Name {
 }
 
 pub fn utf8(b: &[u8]) -> String {
-    std::str::from_utf8(b).unwrap().to_owned()
+    String::from_utf8_lossy(b).into_owned()
 }
@@ -53,7 +53,7 @@ impl Label {
 
                 Ok(Self {
                     str_type: StrType::new(str_type)?,
-                    label: InlineString::new(label),
+                    label: InlineString::try_new(label, s)?,
                 })
             }
             _ => Err(Error::Parse {
@@ -96,8 +96,8 @@ impl Attr {
 
                 Ok(Self {
                     str_type: StrType::new(str_type)?,
-                    label: InlineString::new(label),
-                    attr: InlineString::new(attr),
+                    label: InlineString::try_new(label, s)?,
+                    attr: InlineString::try_new(attr, s)?,
                 })
             }
             _ => Err(Error::Parse {
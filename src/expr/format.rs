@@ -2,8 +2,7 @@ use crate::errors::*;
 use crate::expr;
 use crate::parse_utils::*;
 use crate::read::*;
-
-const UNKNOWN_QUAL: u8 = b'I';
+use crate::seq_utils::*;
 
 #[derive(Debug, Clone)]
 pub struct FormatExpr {
@@ -14,7 +13,19 @@ pub struct FormatExpr {
 enum Expr {
     Literal(Vec<u8>),
     LabelOrAttr(expr::LabelOrAttr),
+    // attribute formatted with a certain number of digits after the decimal point
+    AttrPrecision(expr::Attr, usize),
     Repeat(Box<Expr>, Num),
+    Func(Function, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Function {
+    Revcomp,
+    Upper,
+    Lower,
+    Len,
+    Slice(usize, Option<usize>),
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +49,20 @@ impl FormatExpr {
 
         Ok(res)
     }
+
+    /// Get the string if this format expression only contains literals.
+    pub fn literal(&self) -> Option<Vec<u8>> {
+        let mut res = Vec::new();
+
+        for e in &self.expr {
+            match e {
+                Expr::Literal(s) => res.extend(s),
+                _ => return None,
+            }
+        }
+
+        Some(res)
+    }
 }
 
 fn format_expr(
@@ -70,6 +95,49 @@ fn format_expr(
                 res.extend(read.data(*str_type, *label, *attr)?.to_string().as_bytes());
             }
         },
+        AttrPrecision(
+            expr::Attr {
+                str_type,
+                label,
+                attr,
+            },
+            precision,
+        ) => {
+            let data = read.data(*str_type, *label, *attr)?;
+            res.extend(format!("{:.*}", precision, data).as_bytes());
+        }
+        Func(func, expr) => {
+            let mut inner = Vec::new();
+            format_expr(read, use_qual, expr, &mut inner)?;
+
+            match func {
+                Function::Revcomp => {
+                    // qualities are only reversed
+                    if use_qual {
+                        res.extend(inner.iter().rev());
+                    } else {
+                        res.extend(revcomp(&inner));
+                    }
+                }
+                Function::Upper if !use_qual => res.extend(inner.to_ascii_uppercase()),
+                Function::Lower if !use_qual => res.extend(inner.to_ascii_lowercase()),
+                Function::Upper | Function::Lower => res.extend(inner),
+                Function::Len => {
+                    let len = inner.len().to_string();
+
+                    if use_qual {
+                        res.extend(len.bytes().map(|_| UNKNOWN_QUAL));
+                    } else {
+                        res.extend(len.bytes());
+                    }
+                }
+                Function::Slice(start, end) => {
+                    let end = end.unwrap_or(inner.len()).min(inner.len());
+                    let start = (*start).min(end);
+                    res.extend(&inner[start..end]);
+                }
+            }
+        }
         Repeat(expr, num) => {
             let repeats = match num {
                 Num::Literal(n) => *n,
@@ -106,6 +174,89 @@ fn format_expr(
     Ok(())
 }
 
+/// Parse the expression inside braces, excluding the number of repeats.
+fn parse_inner(left: &[u8], expr: &[u8]) -> Result<Expr> {
+    let trim = |s| {
+        trim_ascii_whitespace(s).ok_or_else(|| Error::InvalidName {
+            string: utf8(s),
+            context: utf8(expr),
+        })
+    };
+    let last = left[left.len() - 1];
+
+    if left[0] == b'\'' && last == b'\'' {
+        return Ok(Expr::Literal(left[1..left.len() - 1].to_owned()));
+    }
+
+    // function calls like `revcomp(seq1.*)`
+    if last == b')' {
+        if let Some(open) = left.iter().position(|&c| c == b'(') {
+            let func = match trim(&left[..open])? {
+                b"revcomp" => Function::Revcomp,
+                b"upper" => Function::Upper,
+                b"lower" => Function::Lower,
+                b"len" => Function::Len,
+                _ => Err(Error::Parse {
+                    string: utf8(&left[..open]),
+                    context: utf8(expr),
+                    reason:
+                        "unknown function. Expected \"revcomp\", \"upper\", \"lower\", or \"len\"",
+                })?,
+            };
+            let inner = parse_inner(trim(&left[open + 1..left.len() - 1])?, expr)?;
+            return Ok(Expr::Func(func, Box::new(inner)));
+        }
+    }
+
+    // slices like `seq1.*[0..8]`
+    if last == b']' {
+        if let Some(open) = left.iter().rposition(|&c| c == b'[') {
+            let range = trim(&left[open + 1..left.len() - 1])?;
+            let err = || Error::Parse {
+                string: utf8(range),
+                context: utf8(expr),
+                reason: "expected a range like \"0..8\", \"..8\", or \"2..\"",
+            };
+            let dots = find_skip_quotes(range, b'.').ok_or_else(err)?;
+            if range.get(dots + 1) != Some(&b'.') {
+                return Err(err());
+            }
+
+            let parse_idx = |s: &[u8]| -> Result<Option<usize>> {
+                match trim_ascii_whitespace(s) {
+                    Some(s) => std::str::from_utf8(s)
+                        .ok()
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .map(Some)
+                        .ok_or_else(err),
+                    None => Ok(None),
+                }
+            };
+            let start = parse_idx(&range[..dots])?.unwrap_or(0);
+            let end = parse_idx(&range[dots + 2..])?;
+
+            let inner = parse_inner(trim(&left[..open])?, expr)?;
+            return Ok(Expr::Func(Function::Slice(start, end), Box::new(inner)));
+        }
+    }
+
+    if let Some(colon) = find_skip_quotes(left, b':') {
+        let attr = trim(&left[..colon])?;
+        let precision = trim_ascii_whitespace(&left[colon + 1..])
+            .and_then(|p| p.strip_prefix(b"."))
+            .and_then(|p| std::str::from_utf8(p).ok())
+            .and_then(|p| p.parse::<usize>().ok())
+            .ok_or_else(|| Error::Parse {
+                string: utf8(&left[colon + 1..]),
+                context: utf8(expr),
+                reason: "expected precision like \".2\"",
+            })?;
+        return Ok(Expr::AttrPrecision(expr::Attr::new(attr)?, precision));
+    }
+
+    Ok(Expr::LabelOrAttr(expr::LabelOrAttr::new(left)?))
+}
+
 fn parse(expr: &[u8]) -> Result<Vec<Expr>> {
     let mut res = Vec::new();
     let mut curr = Vec::new();
@@ -143,11 +294,7 @@ fn parse(expr: &[u8]) -> Result<Vec<Expr>> {
                         context: utf8(expr),
                     })?;
 
-                let e = if left[0] == b'\'' && left[left.len() - 1] == b'\'' {
-                    Expr::Literal(left[1..left.len() - 1].to_owned())
-                } else {
-                    Expr::LabelOrAttr(expr::LabelOrAttr::new(left)?)
-                };
+                let e = parse_inner(left, expr)?;
 
                 if let Some(idx) = idx {
                     let right = trim_ascii_whitespace(&curr[idx + 1..]).ok_or_else(|| {
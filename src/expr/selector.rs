@@ -1,6 +1,17 @@
+use regex::bytes::Regex;
+use rustc_hash::FxHashSet;
+use thread_local::ThreadLocal;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
 use crate::errors::*;
 use crate::expr;
 use crate::inline_string::*;
+use crate::parse_utils::*;
+use crate::patterns::Patterns;
 use crate::read::*;
 
 #[derive(Debug, Clone)]
@@ -10,9 +21,24 @@ pub struct SelectorExpr {
 
 impl SelectorExpr {
     pub fn new(expr_str: &[u8]) -> Result<Self> {
-        Ok(Self {
-            expr: parse(&lex(expr_str)?)?,
-        })
+        let items = lex(expr_str)?;
+
+        // an empty selector matches every read
+        let expr = if items.is_empty() {
+            Expr::True
+        } else {
+            // parse errors do not know the whole expression
+            parse(&items).map_err(|e| match e {
+                Error::Parse { string, reason, .. } if string.is_empty() => Error::Parse {
+                    string: utf8(expr_str),
+                    context: utf8(expr_str),
+                    reason,
+                },
+                e => e,
+            })?
+        };
+
+        Ok(Self { expr })
     }
 
     pub fn matches(&self, read: &Read) -> std::result::Result<bool, NameError> {
@@ -28,7 +54,12 @@ enum Item {
     Or,
     Not,
     Dot,
+    Minus,
+    Tilde,
+    Cmp(CmpOp),
     Label(Vec<u8>),
+    Str(Vec<u8>),
+    Num(Vec<u8>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,6 +70,46 @@ enum Expr {
     Not(Box<Expr>),
     Label(expr::Label),
     Attr(expr::Attr),
+    Cmp(Operand, CmpOp, Operand),
+    Regex(Operand, SelectorRegex),
+    In(Operand, Arc<FxHashSet<Vec<u8>>>),
+}
+
+/// Regex that is compiled once and cloned for each thread.
+#[derive(Clone)]
+struct SelectorRegex {
+    regex: Regex,
+    regex_local: Arc<ThreadLocal<Regex>>,
+}
+
+impl fmt::Debug for SelectorRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.regex.as_str())
+    }
+}
+
+impl PartialEq for SelectorRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Label(expr::Label),
+    Attr(expr::Attr),
+    Len(expr::LabelOrAttr),
+    Literal(Data),
 }
 
 fn matches_rec(expr: &Expr, read: &Read) -> std::result::Result<bool, NameError> {
@@ -76,6 +147,65 @@ fn matches_rec(expr: &Expr, read: &Read) -> std::result::Result<bool, NameError>
             label,
             attr,
         }) => Ok(read.data(*str_type, *label, *attr)?.as_bool()),
+        Cmp(a, op, b) => {
+            let ord = compare(&operand_data(a, read)?, &operand_data(b, read)?)?;
+
+            use CmpOp::*;
+            Ok(match op {
+                Eq => ord == Some(Ordering::Equal),
+                Ne => ord != Some(Ordering::Equal),
+                Lt => ord == Some(Ordering::Less),
+                Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                Gt => ord == Some(Ordering::Greater),
+                Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+            })
+        }
+        Regex(a, SelectorRegex { regex, regex_local }) => {
+            let regex = regex_local.get_or(|| regex.clone());
+            Ok(regex.is_match(&data_bytes(operand_data(a, read)?)))
+        }
+        In(a, set) => Ok(set.contains(&data_bytes(operand_data(a, read)?))),
+    }
+}
+
+/// Get the bytes of a string, or the text representation of other data types.
+fn data_bytes(data: Data) -> Vec<u8> {
+    match data {
+        Data::Bytes(b) => b,
+        _ => data.to_string().into_bytes(),
+    }
+}
+
+fn operand_data(operand: &Operand, read: &Read) -> std::result::Result<Data, NameError> {
+    use Operand::*;
+    match operand {
+        Label(expr::Label { str_type, label }) => {
+            Ok(Data::Bytes(read.substring(*str_type, *label)?.to_owned()))
+        }
+        Attr(expr::Attr {
+            str_type,
+            label,
+            attr,
+        }) => Ok(read.data(*str_type, *label, *attr)?.clone()),
+        Len(expr::LabelOrAttr::Label(expr::Label { str_type, label })) => {
+            Ok(Data::UInt(read.mapping(*str_type, *label)?.len))
+        }
+        Len(expr::LabelOrAttr::Attr(expr::Attr {
+            str_type,
+            label,
+            attr,
+        })) => Ok(Data::UInt(read.data(*str_type, *label, *attr)?.len()?)),
+        Literal(data) => Ok(data.clone()),
+    }
+}
+
+/// Compare bytes with bytes, or numbers (including bools) with numbers.
+fn compare(a: &Data, b: &Data) -> std::result::Result<Option<Ordering>, NameError> {
+    match (a, b) {
+        (Data::Bytes(a), Data::Bytes(b)) => Ok(Some(a.cmp(b))),
+        (Data::Bytes(_), _) => Err(NameError::Type("bytes", b.clone())),
+        (Data::UInt(a), Data::UInt(b)) => Ok(Some(a.cmp(b))),
+        _ => Ok(a.as_float()?.partial_cmp(&b.as_float()?)),
     }
 }
 
@@ -85,13 +215,15 @@ fn lex(expr_str: &[u8]) -> Result<Vec<Item>> {
 
     use Item::*;
 
+    let err = |reason| Error::Parse {
+        string: utf8(expr_str),
+        context: utf8(expr_str),
+        reason,
+    };
+
     let write_curr = |res: &mut Vec<Item>, curr: &mut Vec<u8>, expect_empty| {
         if (expect_empty && !curr.is_empty()) || (!expect_empty && curr.is_empty()) {
-            Err(Error::Parse {
-                string: utf8(expr_str),
-                context: utf8(expr_str),
-                reason: "invalid boolean expression",
-            })?;
+            Err(err("invalid boolean expression"))?;
         }
 
         if !curr.is_empty() {
@@ -102,40 +234,125 @@ fn lex(expr_str: &[u8]) -> Result<Vec<Item>> {
         Ok(())
     };
 
-    for &c in expr_str {
+    // the previous item may be a literal or a closing parenthesis instead of a name
+    let flush_curr = |res: &mut Vec<Item>, curr: &mut Vec<u8>| {
+        if !curr.is_empty() {
+            res.push(Label(curr.clone()));
+            curr.clear();
+        }
+    };
+
+    let mut i = 0;
+
+    while i < expr_str.len() {
+        let c = expr_str[i];
+        let next = expr_str.get(i + 1).cloned();
+
         match c {
             b'(' => {
-                write_curr(&mut res, &mut curr, true)?;
+                // function calls like `len(seq1.*)` have a name before the parentheses
+                flush_curr(&mut res, &mut curr);
                 res.push(LeftParens);
             }
             b')' => {
-                write_curr(&mut res, &mut curr, false)?;
+                flush_curr(&mut res, &mut curr);
                 res.push(RightParens);
             }
             b'&' => {
-                write_curr(&mut res, &mut curr, false)?;
+                flush_curr(&mut res, &mut curr);
                 res.push(And);
             }
             b'|' => {
-                write_curr(&mut res, &mut curr, false)?;
+                flush_curr(&mut res, &mut curr);
                 res.push(Or);
             }
+            b'!' if next == Some(b'=') => {
+                flush_curr(&mut res, &mut curr);
+                res.push(Cmp(CmpOp::Ne));
+                i += 1;
+            }
             b'!' => {
                 write_curr(&mut res, &mut curr, true)?;
                 res.push(Not);
             }
+            b'=' if next == Some(b'=') => {
+                flush_curr(&mut res, &mut curr);
+                res.push(Cmp(CmpOp::Eq));
+                i += 1;
+            }
+            b'<' | b'>' => {
+                flush_curr(&mut res, &mut curr);
+                let or_equal = next == Some(b'=');
+                res.push(Cmp(match (c, or_equal) {
+                    (b'<', false) => CmpOp::Lt,
+                    (b'<', true) => CmpOp::Le,
+                    (_, false) => CmpOp::Gt,
+                    (_, true) => CmpOp::Ge,
+                }));
+                i += or_equal as usize;
+            }
+            b'-' => {
+                write_curr(&mut res, &mut curr, true)?;
+                res.push(Minus);
+            }
+            b'~' => {
+                flush_curr(&mut res, &mut curr);
+                res.push(Tilde);
+            }
+            b'\'' | b'"' => {
+                write_curr(&mut res, &mut curr, true)?;
+                let mut s = Vec::new();
+                let mut escape = false;
+                i += 1;
+
+                loop {
+                    let Some(&b) = expr_str.get(i) else {
+                        Err(err("unterminated string literal"))?
+                    };
+
+                    match b {
+                        b'\\' if !escape => escape = true,
+                        _ if b == c && !escape => break,
+                        _ => {
+                            escape = false;
+                            s.push(b);
+                        }
+                    }
+
+                    i += 1;
+                }
+
+                res.push(Str(s));
+            }
+            b'0'..=b'9' if curr.is_empty() && res.last() != Some(&Dot) => {
+                let len = expr_str[i..]
+                    .iter()
+                    .take_while(|&&b| b.is_ascii_digit() || b == b'.')
+                    .count();
+                res.push(Num(expr_str[i..i + len].to_owned()));
+                i += len;
+                continue;
+            }
             b'.' => {
-                write_curr(&mut res, &mut curr, false)?;
-                res.push(Dot);
+                // there may be whitespace between a name and the dot
+                if curr.is_empty() && matches!(res.last(), Some(Label(_))) {
+                    res.push(Dot);
+                } else {
+                    write_curr(&mut res, &mut curr, false)?;
+                    res.push(Dot);
+                }
             }
             b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'*' => curr.push(c),
-            _ if c.is_ascii_whitespace() => (),
+            // whitespace separates keywords like `in` from names
+            _ if c.is_ascii_whitespace() => flush_curr(&mut res, &mut curr),
             _ => Err(Error::Parse {
                 string: (c as char).to_string(),
                 context: utf8(expr_str),
                 reason: "invalid character",
             })?,
         }
+
+        i += 1;
     }
 
     if !curr.is_empty() {
@@ -148,8 +365,14 @@ fn lex(expr_str: &[u8]) -> Result<Vec<Item>> {
 fn parse(items: &[Item]) -> Result<Expr> {
     let items = unwrap_parens(items);
 
+    // operators must be separated by operands, so `seq1.a & & seq1.b`, `seq1.a &`, `!`, and `()`
+    // leave an empty operand
     if items.is_empty() {
-        return Ok(Expr::True);
+        Err(Error::Parse {
+            string: "".to_owned(),
+            context: "".to_owned(),
+            reason: "invalid boolean expression",
+        })?;
     }
 
     if items.len() == 3 {
@@ -157,7 +380,7 @@ fn parse(items: &[Item]) -> Result<Expr> {
         if let (Label(str_type), Dot, Label(label)) = (&items[0], &items[1], &items[2]) {
             return Ok(Expr::Label(expr::Label {
                 str_type: StrType::new(&str_type)?,
-                label: InlineString::new(label),
+                label: InlineString::try_new(label, label)?,
             }));
         }
     }
@@ -169,8 +392,8 @@ fn parse(items: &[Item]) -> Result<Expr> {
         {
             return Ok(Expr::Attr(expr::Attr {
                 str_type: StrType::new(&str_type)?,
-                label: InlineString::new(label),
-                attr: InlineString::new(attr),
+                label: InlineString::try_new(label, label)?,
+                attr: InlineString::try_new(attr, attr)?,
             }));
         }
     }
@@ -194,13 +417,174 @@ fn parse(items: &[Item]) -> Result<Expr> {
     }
 
     if let Item::Not = items[0] {
-        Ok(Expr::Not(Box::new(parse(&items[1..])?)))
+        return Ok(Expr::Not(Box::new(parse(&items[1..])?)));
+    }
+
+    let mut layer = 0;
+    for (idx, item) in items.iter().enumerate() {
+        match item {
+            Item::LeftParens => layer += 1,
+            Item::RightParens => layer -= 1,
+            Item::Cmp(op) if layer == 0 => {
+                return Ok(Expr::Cmp(
+                    parse_operand(&items[..idx])?,
+                    *op,
+                    parse_operand(&items[idx + 1..])?,
+                ));
+            }
+            Item::Tilde if layer == 0 => {
+                let [Item::Str(regex)] = &items[idx + 1..] else {
+                    Err(Error::Parse {
+                        string: "".to_owned(),
+                        context: "".to_owned(),
+                        reason: "expected a quoted regex after \"~\"",
+                    })?
+                };
+                let regex = std::str::from_utf8(regex)
+                    .ok()
+                    .and_then(|r| Regex::new(r).ok())
+                    .ok_or_else(|| Error::Parse {
+                        string: utf8(regex),
+                        context: utf8(regex),
+                        reason: "invalid regex",
+                    })?;
+
+                return Ok(Expr::Regex(
+                    parse_operand(&items[..idx])?,
+                    SelectorRegex {
+                        regex,
+                        regex_local: Arc::new(ThreadLocal::new()),
+                    },
+                ));
+            }
+            // `in` is a keyword unless it is part of a name like `seq1.in`
+            Item::Label(l)
+                if layer == 0
+                    && l == b"in"
+                    && idx > 0
+                    && items[idx - 1] != Item::Dot
+                    && items.get(idx + 1) != Some(&Item::Dot) =>
+            {
+                let [Item::Str(file)] = &items[idx + 1..] else {
+                    Err(Error::Parse {
+                        string: "".to_owned(),
+                        context: "".to_owned(),
+                        reason: "expected a quoted file path after \"in\"",
+                    })?
+                };
+
+                return Ok(Expr::In(
+                    parse_operand(&items[..idx])?,
+                    Arc::new(load_set(&utf8(file))?),
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    Err(Error::Parse {
+        string: "".to_owned(),
+        context: "".to_owned(),
+        reason: "invalid boolean expression",
+    })
+}
+
+/// Load a set of strings from a file.
+///
+/// Patterns YAML files (`.yaml` or `.yml`) must only contain literal patterns. Other files must
+/// contain one string per line, and they can be gzip compressed (`.gz`).
+fn load_set(file: &str) -> Result<FxHashSet<Vec<u8>>> {
+    let file_err = |e: std::io::Error| Error::FileIo {
+        file: file.to_owned(),
+        source: Box::new(e),
+    };
+
+    if file.ends_with(".yaml") || file.ends_with(".yml") {
+        let patterns = Patterns::from_yaml(std::fs::read(file).map_err(file_err)?)?;
+
+        return patterns
+            .patterns()
+            .iter()
+            .map(|p| {
+                p.expr.literal().ok_or_else(|| Error::Parse {
+                    string: file.to_owned(),
+                    context: file.to_owned(),
+                    reason: "patterns must be literal strings when checking membership",
+                })
+            })
+            .collect();
+    }
+
+    let f = std::fs::File::open(file).map_err(file_err)?;
+    let reader: Box<dyn BufRead> = if file.ends_with(".gz") {
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(f)))
     } else {
-        Err(Error::Parse {
+        Box::new(BufReader::new(f))
+    };
+
+    let mut res = FxHashSet::default();
+
+    for line in reader.split(b'\n') {
+        let line = line.map_err(file_err)?;
+        if let Some(line) = trim_ascii_whitespace(&line) {
+            res.insert(line.to_owned());
+        }
+    }
+
+    Ok(res)
+}
+
+fn parse_operand(items: &[Item]) -> Result<Operand> {
+    use Item::*;
+    let items = unwrap_parens(items);
+
+    match items {
+        [Label(str_type), Dot, Label(label)] => Ok(Operand::Label(expr::Label {
+            str_type: StrType::new(str_type)?,
+            label: InlineString::try_new(label, label)?,
+        })),
+        [Label(str_type), Dot, Label(label), Dot, Label(attr)] => Ok(Operand::Attr(expr::Attr {
+            str_type: StrType::new(str_type)?,
+            label: InlineString::try_new(label, label)?,
+            attr: InlineString::try_new(attr, attr)?,
+        })),
+        [Label(f), LeftParens, inner @ .., RightParens] if f == b"len" => {
+            match parse_operand(inner)? {
+                Operand::Label(l) => Ok(Operand::Len(expr::LabelOrAttr::Label(l))),
+                Operand::Attr(a) => Ok(Operand::Len(expr::LabelOrAttr::Attr(a))),
+                _ => Err(Error::Parse {
+                    string: "".to_owned(),
+                    context: "".to_owned(),
+                    reason: "expected type.label or type.label.attr in len(...)",
+                }),
+            }
+        }
+        [Str(s)] => Ok(Operand::Literal(Data::Bytes(s.clone()))),
+        [Num(n)] => Ok(Operand::Literal(parse_num(n, false)?)),
+        [Minus, Num(n)] => Ok(Operand::Literal(parse_num(n, true)?)),
+        _ => Err(Error::Parse {
             string: "".to_owned(),
             context: "".to_owned(),
-            reason: "invalid boolean expression",
-        })
+            reason: "invalid operand in comparison",
+        }),
+    }
+}
+
+fn parse_num(n: &[u8], negative: bool) -> Result<Data> {
+    let s = std::str::from_utf8(n).unwrap();
+    let err = || Error::Parse {
+        string: utf8(n),
+        context: utf8(n),
+        reason: "invalid number",
+    };
+
+    if n.contains(&b'.') {
+        let x = s.parse::<f64>().map_err(|_| err())?;
+        Ok(Data::Float(if negative { -x } else { x }))
+    } else if negative {
+        Ok(Data::Int(-s.parse::<isize>().map_err(|_| err())?))
+    } else {
+        Ok(Data::UInt(s.parse::<usize>().map_err(|_| err())?))
     }
 }
 
@@ -241,13 +625,136 @@ where
     }
 }
 
-fn unwrap_parens(items: &[Item]) -> &[Item] {
-    let c1 = items.iter().take_while(|&i| i == &Item::LeftParens).count();
-    let c2 = items
-        .iter()
-        .rev()
-        .take_while(|&i| i == &Item::RightParens)
-        .count();
-    let c = c1.min(c2);
-    &items[c..items.len() - c]
+fn unwrap_parens(mut items: &[Item]) -> &[Item] {
+    // only remove parentheses that enclose the whole expression, like `(seq1.a & seq1.b)` but
+    // not `(seq1.a) & (seq1.b)`
+    while let [Item::LeftParens, inner @ .., Item::RightParens] = items {
+        let mut layer = 0usize;
+        let balanced = inner.iter().all(|item| {
+            match item {
+                Item::LeftParens => layer += 1,
+                Item::RightParens if layer == 0 => return false,
+                Item::RightParens => layer -= 1,
+                _ => (),
+            }
+            true
+        });
+
+        if !balanced {
+            break;
+        }
+        items = inner;
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fastq::Origin;
+
+    fn read() -> Read {
+        Read::from_fastq1(b"r1 1:N:0", b"ACGT", None, Arc::new(Origin::Bytes), 0)
+    }
+
+    fn matches(expr: &str) -> bool {
+        SelectorExpr::new(expr.as_bytes())
+            .unwrap()
+            .matches(&read())
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_boolean_exprs() {
+        for expr in [
+            "",
+            "seq1.*",
+            "seq1.a.b",
+            "!seq1.a",
+            "seq1.a & seq1.b",
+            "seq1.a & !seq1.b | seq1.c",
+            "(seq1.a | seq1.b) & seq1.c",
+            "(seq1.a) & (seq1.b)",
+            "((seq1.a))",
+        ] {
+            assert!(SelectorExpr::new(expr.as_bytes()).is_ok(), "{expr}");
+        }
+
+        assert_eq!(
+            SelectorExpr::new(b"(seq1.a) & (seq1.b)").unwrap().expr,
+            Expr::And(vec![
+                Expr::Label(crate::label!(seq1.a)),
+                Expr::Label(crate::label!(seq1.b)),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_boolean_exprs() {
+        for expr in [
+            "seq1.a & & seq1.b",
+            "seq1.a | | seq1.b",
+            "seq1.a &",
+            "& seq1.a",
+            "!",
+            "()",
+            "seq1.a seq1.b",
+            "seq1.a ! seq1.b",
+            "(seq1.a",
+            "seq1.a)",
+            "seq1",
+        ] {
+            assert!(SelectorExpr::new(expr.as_bytes()).is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(matches("len(seq1.*) == 4"));
+        assert!(matches("len(seq1.*) >= 4 & len(seq1.*) <= 4"));
+        assert!(!matches("len(seq1.*) > 4"));
+        assert!(matches("len(seq1.*) != 3"));
+        assert!(matches("len(seq1.*) > -1.5"));
+        assert!(matches("seq1.* == 'ACGT'"));
+        assert!(matches("(len(seq1.*) < 5) & (seq1.* != \"AC\")"));
+
+        for expr in [
+            "len(seq1.*) == == 4",
+            "len(seq1.*) ==",
+            "== 4",
+            "len(3) > 1",
+            "len(seq1.*) > 1.2.3",
+            "seq1.* == 'ACGT",
+        ] {
+            assert!(SelectorExpr::new(expr.as_bytes()).is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn regex_and_membership() {
+        assert!(matches("name1.* ~ ':N:'"));
+        assert!(!matches("name1.* ~ ':Y:'"));
+        assert!(matches("seq1.a | name1.* ~ '^r1'"));
+
+        let file = std::env::temp_dir().join(format!("whitelist_{}.txt", std::process::id()));
+        let file = file.to_str().unwrap();
+        std::fs::write(file, "AAAA\nACGT\n").unwrap();
+        assert!(matches(&format!("seq1.* in \"{file}\"")));
+        assert!(!matches(&format!("!(seq1.* in '{file}')")));
+
+        for expr in [
+            "name1.* ~",
+            "name1.* ~ name1.*",
+            "name1.* ~ '('",
+            "seq1.* in",
+            "seq1.* in whitelist",
+            "seq1.* in 'missing_whitelist.txt'",
+        ] {
+            assert!(SelectorExpr::new(expr.as_bytes()).is_err(), "{expr}");
+        }
+
+        std::fs::remove_file(file).unwrap();
+    }
 }
@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use crate::errors::*;
+use crate::fastq::*;
+
+// fasta records are parsed by the same reader as fastq records, without quality scores
+pub type Fasta1Reads<'a> = Fastq1Reads<'a>;
+pub type Fasta2Reads = Fastq2Reads;
+
+/// Create a read iterator over fasta records from a file.
+///
+/// The resulting reads do not have quality scores.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fasta1(file: impl AsRef<str>, chunk_size: usize) -> Result<Fasta1Reads<'static>> {
+    iter_fastq1(file, chunk_size)
+}
+
+/// Create a read iterator over interleaved paired-end fasta records from a file.
+///
+/// The resulting reads do not have quality scores.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fasta_interleaved(
+    file: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<Fasta1Reads<'static>> {
+    iter_fastq_interleaved(file, chunk_size)
+}
+
+/// Create a read iterator over paired-end fasta records from two different files.
+///
+/// The resulting reads do not have quality scores.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fasta2(
+    file1: impl AsRef<str>,
+    file2: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<Fasta2Reads> {
+    iter_fastq2(file1, file2, chunk_size)
+}
+
+/// Create a read iterator over fasta records from a byte slice.
+#[must_use]
+pub fn iter_fasta1_bytes<'a>(bytes: &'a [u8]) -> Result<Fasta1Reads<'a>> {
+    iter_fastq1_bytes(bytes)
+}
+
+/// Create a read iterator over interleaved paired-end fasta records from a byte slice.
+#[must_use]
+pub fn iter_fasta_interleaved_bytes<'a>(bytes: &'a [u8]) -> Result<Fasta1Reads<'a>> {
+    iter_fastq_interleaved_bytes(bytes)
+}
+
+/// Write a fasta record.
+///
+/// If `line_width` is specified, then the sequence is wrapped so each line has at most
+/// `line_width` characters.
+pub fn write_fasta_record(
+    writer: &mut (dyn Write + std::marker::Send),
+    record: (&[u8], &[u8]),
+    line_width: Option<usize>,
+) -> std::io::Result<()> {
+    writer.write_all(b">")?;
+    writer.write_all(record.0)?;
+    writer.write_all(b"\n")?;
+
+    match line_width {
+        Some(w) if w > 0 => {
+            for line in record.1.chunks(w) {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        _ => {
+            writer.write_all(record.1)?;
+            writer.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
@@ -8,6 +8,7 @@ use std::sync::{Arc, Mutex};
 use crate::errors::*;
 use crate::iter::*;
 use crate::read::*;
+use crate::seq_utils::UNKNOWN_QUAL;
 
 pub struct Fastq1Reads<'a> {
     reader: Mutex<Box<dyn FastxReader + 'a>>,
@@ -22,7 +23,7 @@ impl<'a> Reads for Fastq1Reads<'a> {
         let mut res = Vec::with_capacity(self.chunk_size);
         let mut record1_id = Vec::new();
         let mut record1_seq = Vec::new();
-        let mut record1_qual = Vec::new();
+        let mut record1_qual = None;
 
         let mut reader = self.reader.lock().unwrap();
 
@@ -39,15 +40,14 @@ impl<'a> Reads for Fastq1Reads<'a> {
                     record1_id.extend_from_slice(record1.id());
                     record1_seq.clear();
                     record1_seq.extend_from_slice(&record1.seq());
-                    record1_qual.clear();
-                    record1_qual.extend_from_slice(record1.qual().unwrap());
+                    record1_qual = record1.qual().map(|q| q.to_owned());
                 } else {
                     let idx = self.idx.fetch_add(1, Ordering::Relaxed);
 
                     res.push(Read::from_fastq1(
                         record1.id(),
                         &record1.seq(),
-                        record1.qual().unwrap(),
+                        record1.qual(),
                         Arc::clone(&self.origin),
                         idx,
                     ));
@@ -70,12 +70,12 @@ impl<'a> Reads for Fastq1Reads<'a> {
                 res.push(Read::from_fastq2(
                     &record1_id,
                     &record1_seq,
-                    &record1_qual,
+                    record1_qual.as_deref(),
                     Arc::clone(&self.origin),
                     idx,
                     record2.id(),
                     &record2.seq(),
-                    record2.qual().unwrap(),
+                    record2.qual(),
                     Arc::clone(&self.origin),
                     idx + 1,
                 ));
@@ -129,12 +129,12 @@ impl Reads for Fastq2Reads {
             res.push(Read::from_fastq2(
                 record1.id(),
                 &record1.seq(),
-                record1.qual().unwrap(),
+                record1.qual(),
                 Arc::clone(&self.origin1),
                 idx,
                 record2.id(),
                 &record2.seq(),
-                record2.qual().unwrap(),
+                record2.qual(),
                 Arc::clone(&self.origin2),
                 idx,
             ));
@@ -148,6 +148,283 @@ impl Reads for Fastq2Reads {
     }
 }
 
+pub struct FastqMultiReads {
+    readers: Mutex<Vec<Box<dyn FastxReader>>>,
+    // string types for the name (if it is kept) and the sequence from each file
+    str_types: Vec<(Option<StrType>, StrType)>,
+    origins: Vec<Arc<Origin>>,
+    idx: AtomicUsize,
+    chunk_size: usize,
+}
+
+impl Reads for FastqMultiReads {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut res = Vec::with_capacity(self.chunk_size);
+
+        let mut readers = self.readers.lock().unwrap();
+        let (first, rest) = readers.split_at_mut(1);
+
+        for _ in 0..self.chunk_size {
+            let Some(record1) = first[0].next() else {
+                // all files must have the same number of records
+                for (reader, origin) in rest.iter_mut().zip(&self.origins[1..]) {
+                    if reader.next().is_some() {
+                        Err(Error::UnpairedRead(format!(
+                            "\"{}\" and \"{}\"",
+                            &*self.origins[0], &**origin
+                        )))?
+                    }
+                }
+                break;
+            };
+            let record1 = record1.map_err(|e| Error::ParseRecord {
+                origin: (*self.origins[0]).clone(),
+                idx: self.idx.load(Ordering::Relaxed),
+                source: Box::new(e),
+            })?;
+            let idx = self.idx.fetch_add(1, Ordering::Relaxed);
+
+            let mut read = Read::from_fastq1(
+                record1.id(),
+                &record1.seq(),
+                record1.qual(),
+                Arc::clone(&self.origins[0]),
+                idx,
+            );
+
+            for ((reader, origin), &(name_type, seq_type)) in rest
+                .iter_mut()
+                .zip(&self.origins[1..])
+                .zip(&self.str_types[1..])
+            {
+                let Some(record) = reader.next() else {
+                    Err(Error::UnpairedRead(format!(
+                        "\"{}\" and \"{}\"",
+                        &*self.origins[0], &**origin
+                    )))?
+                };
+                let record = record.map_err(|e| Error::ParseRecord {
+                    origin: (**origin).clone(),
+                    idx,
+                    source: Box::new(e),
+                })?;
+
+                if !same_record_name(record1.id(), record.id()) {
+                    Err(Error::UnpairedRead(format!(
+                        "\"{}\" and \"{}\" for records \"{}\" and \"{}\"",
+                        &*self.origins[0],
+                        &**origin,
+                        utf8(record1.id()),
+                        utf8(record.id())
+                    )))?
+                }
+
+                if let Some(name_type) = name_type {
+                    read.add_str_mappings(
+                        name_type,
+                        StrMappings::new(record.id().to_owned(), Arc::clone(origin), idx),
+                    )
+                    .unwrap();
+                }
+
+                read.add_str_mappings(
+                    seq_type,
+                    StrMappings::from_record(&record.seq(), record.qual(), Arc::clone(origin), idx),
+                )
+                .unwrap();
+            }
+
+            res.push(read);
+        }
+
+        Ok(res)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Check whether two record names refer to the same read, ignoring everything after the first
+/// whitespace and read number suffixes like `/1`.
+fn same_record_name(a: &[u8], b: &[u8]) -> bool {
+    fn base_name(name: &[u8]) -> &[u8] {
+        let end = name
+            .iter()
+            .position(|c| c.is_ascii_whitespace())
+            .unwrap_or(name.len());
+        let name = &name[..end];
+
+        match name {
+            [rest @ .., b'/', c] if c.is_ascii_digit() => rest,
+            _ => name,
+        }
+    }
+
+    base_name(a) == base_name(b)
+}
+
+fn iter_fastq_multi(
+    files: &[(Option<StrType>, StrType, &str)],
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    let readers = files
+        .iter()
+        .map(|&(_, _, file)| {
+            parse_fastx_file(file).map_err(|e| Error::FileIo {
+                file: file.to_owned(),
+                source: Box::new(e),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(FastqMultiReads {
+        readers: Mutex::new(readers),
+        str_types: files
+            .iter()
+            .map(|&(name_type, seq_type, _)| (name_type, seq_type))
+            .collect(),
+        origins: files
+            .iter()
+            .map(|&(_, _, file)| Arc::new(Origin::File(file.to_owned())))
+            .collect(),
+        idx: AtomicUsize::new(0),
+        chunk_size,
+    })
+}
+
+/// Create a read iterator over fastq records from a list of files, one file for each
+/// sequence segment.
+///
+/// The records from the `i`-th file (starting from 1) are stored as `name{i}` and `seq{i}`, so
+/// three files result in reads with `seq1`, `seq2`, and `seq3`. All files must have the same
+/// number of records.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fastqn(
+    files: impl IntoIterator<Item = impl AsRef<str>>,
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    let files = files.into_iter().collect::<Vec<_>>();
+    assert!(
+        !files.is_empty(),
+        "At least one file is required for iter_fastqn"
+    );
+
+    iter_fastq_multi(
+        &files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                (
+                    Some(StrType::Name(i + 1)),
+                    StrType::Seq(i + 1),
+                    file.as_ref(),
+                )
+            })
+            .collect::<Vec<_>>(),
+        chunk_size,
+    )
+}
+
+/// Create a read iterator over fastq records and index 1 (I1) fastq records from two different
+/// files.
+///
+/// Index sequences are stored as `index1` and their record names must match `name1`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fastq1_index1(
+    file1: impl AsRef<str>,
+    index1: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    iter_fastq_multi(
+        &[
+            (Some(StrType::Name(1)), StrType::Seq(1), file1.as_ref()),
+            (None, StrType::Index(1), index1.as_ref()),
+        ],
+        chunk_size,
+    )
+}
+
+/// Create a read iterator over fastq records and dual index (I1 and I2) fastq records from three
+/// different files.
+///
+/// Index sequences are stored as `index1` and `index2` and their record names must match `name1`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fastq1_index2(
+    file1: impl AsRef<str>,
+    index1: impl AsRef<str>,
+    index2: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    iter_fastq_multi(
+        &[
+            (Some(StrType::Name(1)), StrType::Seq(1), file1.as_ref()),
+            (None, StrType::Index(1), index1.as_ref()),
+            (None, StrType::Index(2), index2.as_ref()),
+        ],
+        chunk_size,
+    )
+}
+
+/// Create a read iterator over paired-end fastq records and index 1 (I1) fastq records from
+/// three different files.
+///
+/// Index sequences are stored as `index1` and their record names must match `name1`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fastq2_index1(
+    file1: impl AsRef<str>,
+    file2: impl AsRef<str>,
+    index1: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    iter_fastq_multi(
+        &[
+            (Some(StrType::Name(1)), StrType::Seq(1), file1.as_ref()),
+            (Some(StrType::Name(2)), StrType::Seq(2), file2.as_ref()),
+            (None, StrType::Index(1), index1.as_ref()),
+        ],
+        chunk_size,
+    )
+}
+
+/// Create a read iterator over paired-end fastq records and dual index (I1 and I2) fastq records
+/// from four different files.
+///
+/// Index sequences are stored as `index1` and `index2` and their record names must match `name1`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_fastq2_index2(
+    file1: impl AsRef<str>,
+    file2: impl AsRef<str>,
+    index1: impl AsRef<str>,
+    index2: impl AsRef<str>,
+    chunk_size: usize,
+) -> Result<FastqMultiReads> {
+    iter_fastq_multi(
+        &[
+            (Some(StrType::Name(1)), StrType::Seq(1), file1.as_ref()),
+            (Some(StrType::Name(2)), StrType::Seq(2), file2.as_ref()),
+            (None, StrType::Index(1), index1.as_ref()),
+            (None, StrType::Index(2), index2.as_ref()),
+        ],
+        chunk_size,
+    )
+}
+
 /// Create a read iterator over fastq records from a file.
 ///
 /// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
@@ -243,17 +520,22 @@ pub fn iter_fastq_interleaved_bytes<'a>(bytes: &'a [u8]) -> Result<Fastq1Reads<'
     })
 }
 
+/// Write a fastq record, using a placeholder quality score for each base if there are no
+/// quality scores (e.g., for reads from fasta files).
 pub fn write_fastq_record(
     writer: &mut (dyn Write + std::marker::Send),
-    record: (&[u8], &[u8], &[u8]),
-) {
-    writer.write_all(b"@").unwrap();
-    writer.write_all(&record.0).unwrap();
-    writer.write_all(b"\n").unwrap();
-    writer.write_all(&record.1).unwrap();
-    writer.write_all(b"\n+\n").unwrap();
-    writer.write_all(&record.2).unwrap();
-    writer.write_all(b"\n").unwrap();
+    record: FastqRecord,
+) -> std::io::Result<()> {
+    writer.write_all(b"@")?;
+    writer.write_all(&record.0)?;
+    writer.write_all(b"\n")?;
+    writer.write_all(&record.1)?;
+    writer.write_all(b"\n+\n")?;
+    match record.2 {
+        Some(qual) => writer.write_all(qual)?,
+        None => writer.write_all(&vec![UNKNOWN_QUAL; record.1.len()])?,
+    }
+    writer.write_all(b"\n")
 }
 
 #[derive(Debug, Clone)]
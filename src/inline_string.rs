@@ -1,60 +1,145 @@
+use rustc_hash::FxHashSet;
+
+use thread_local::ThreadLocal;
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
+
+use crate::errors::*;
 
-const LEN: usize = 16usize;
+/// All strings that have been interned.
+///
+/// Interned strings are leaked, so they live for the rest of the program. This is fine because
+/// the set of labels and attribute names is small and fixed by the expressions and patterns.
+static INTERNER: OnceLock<RwLock<FxHashSet<&'static str>>> = OnceLock::new();
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
-#[repr(align(8))]
+/// The interned default label `*`, which is used by every read.
+static STAR: OnceLock<InlineString> = OnceLock::new();
+
+/// An interned string used for label and attribute names.
+///
+/// Each unique string is only stored once, so copying, comparing for equality, and hashing only
+/// need the pointer to the string, regardless of the length of the string.
+#[derive(Copy, Clone)]
 pub struct InlineString {
-    data: [u8; LEN],
+    data: &'static str,
 }
 
 impl InlineString {
     pub fn new(s: &[u8]) -> Self {
-        assert!(
-            s.len() <= LEN,
-            "The length of the string \"{}\" must be less than or equal to {LEN}",
-            std::str::from_utf8(s).unwrap()
-        );
+        Self::try_new(s, s).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create an interned string, or an error if the string is not valid UTF-8.
+    ///
+    /// The `context` is the expression or record that contains the string, for error messages.
+    pub fn try_new(s: &[u8], context: &[u8]) -> Result<Self> {
+        let s = std::str::from_utf8(s).map_err(|_| Error::InvalidName {
+            string: utf8(s),
+            context: utf8(context),
+        })?;
+        let interner = INTERNER.get_or_init(|| RwLock::new(FxHashSet::default()));
+
+        if let Some(&data) = interner.read().unwrap().get(s) {
+            return Ok(Self { data });
+        }
+
+        // another thread may have interned the same string, so check again with the write lock
+        let mut interner = interner.write().unwrap();
+        if let Some(&data) = interner.get(s) {
+            return Ok(Self { data });
+        }
 
-        let mut data = [0u8; LEN];
-        s.iter().enumerate().for_each(|(i, &b)| data[i] = b);
+        let data: &'static str = Box::leak(s.to_owned().into_boxed_str());
+        interner.insert(data);
+        Ok(Self { data })
+    }
 
-        Self { data }
+    /// The default label `*`, without going through the interner after the first call.
+    pub fn star() -> Self {
+        *STAR.get_or_init(|| Self::new(b"*"))
     }
 
-    pub fn bytes<'a>(&'a self) -> impl Iterator<Item = u8> + 'a {
-        self.data[..self.len()].iter().cloned()
+    pub fn bytes(&self) -> impl Iterator<Item = u8> + 'static {
+        self.data.bytes()
     }
 
-    pub fn as_str(&self) -> &str {
-        std::str::from_utf8(&self.data[..self.len()]).unwrap()
+    pub fn as_str(&self) -> &'static str {
+        self.data
     }
 
     pub fn len(&self) -> usize {
-        let mut len = 0;
-        while len < LEN && self.data[len] != 0 {
-            len += 1;
-        }
-        len
+        self.data.len()
+    }
+}
+
+impl PartialEq for InlineString {
+    fn eq(&self, other: &Self) -> bool {
+        // interned strings are equal if and only if they are the same allocation
+        std::ptr::eq(self.data, other.data)
+    }
+}
+
+impl Eq for InlineString {}
+
+impl Hash for InlineString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.data.as_ptr() as usize).hash(state);
+    }
+}
+
+impl Ord for InlineString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // order by content so the order does not depend on the allocation addresses
+        self.data.cmp(other.data)
+    }
+}
+
+impl PartialOrd for InlineString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl fmt::Debug for InlineString {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "\"{}\"",
-            std::str::from_utf8(&self.data[..self.len()]).unwrap()
-        )
+        write!(f, "\"{}\"", self.data)
     }
 }
 
 impl fmt::Display for InlineString {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            std::str::from_utf8(&self.data[..self.len()]).unwrap()
-        )
+        write!(f, "{}", self.data)
+    }
+}
+
+/// Interned indexed labels (`label_0`, `label_1`, etc.) that are created on demand.
+///
+/// Each thread caches the labels it has seen, so the interner is only used once for each index.
+pub struct IndexedLabels {
+    prefix: InlineString,
+    cache: ThreadLocal<RefCell<Vec<InlineString>>>,
+}
+
+impl IndexedLabels {
+    pub fn new(prefix: InlineString) -> Self {
+        Self {
+            prefix,
+            cache: ThreadLocal::new(),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> InlineString {
+        let mut cache = self.cache.get_or_default().borrow_mut();
+
+        while cache.len() <= i {
+            let label = format!("{}_{}", self.prefix, cache.len());
+            cache.push(InlineString::new(label.as_bytes()));
+        }
+
+        cache[i]
     }
 }
@@ -7,13 +7,23 @@ use crate::errors::*;
 use crate::expr::*;
 use crate::patterns::*;
 use crate::read::*;
+use crate::sam::SamTag;
 
 pub mod trim_reads;
 use trim_reads::*;
 
+pub mod collect_reads;
+use collect_reads::*;
+
 pub mod collect_fastq_reads;
 use collect_fastq_reads::*;
 
+pub mod collect_fasta_reads;
+use collect_fasta_reads::*;
+
+pub mod collect_sam_reads;
+use collect_sam_reads::*;
+
 pub mod for_each_reads;
 use for_each_reads::*;
 
@@ -26,6 +36,9 @@ use set_reads::*;
 pub mod length_in_bounds_reads;
 use length_in_bounds_reads::*;
 
+pub mod attr_in_bounds_reads;
+use attr_in_bounds_reads::*;
+
 pub mod retain_reads;
 use retain_reads::*;
 
@@ -47,6 +60,24 @@ use take_reads::*;
 pub mod match_polyx_reads;
 use match_polyx_reads::*;
 
+pub mod match_quality_reads;
+use match_quality_reads::*;
+
+pub mod quality_stats_reads;
+use quality_stats_reads::*;
+
+pub mod correct_barcode_reads;
+use correct_barcode_reads::*;
+
+pub mod match_all_reads;
+use match_all_reads::*;
+
+pub mod match_linked_reads;
+use match_linked_reads::*;
+
+pub mod split_reads;
+use split_reads::*;
+
 pub mod intersect_union_reads;
 use intersect_union_reads::*;
 
@@ -165,9 +196,32 @@ pub trait Reads: Send + Sync {
         LengthInBoundsReads::new(self, selector_expr, transform_expr, bounds)
     }
 
+    /// Check whether a numeric attribute is within the specified bounds.
+    ///
+    /// This will set `new_attr` to a boolean indicating whether `attr` is in the specified
+    /// bounds, so it can be used in selector expressions. Non-numeric attributes are out of bounds.
+    ///
+    /// Example: `attr_in_bounds(sel!(), attr!(seq1.*.ee), attr!(seq1.*.low_ee), ..=1.0)`.
+    #[must_use]
+    fn attr_in_bounds<B>(
+        self,
+        selector_expr: SelectorExpr,
+        attr: Attr,
+        new_attr: Attr,
+        bounds: B,
+    ) -> AttrInBoundsReads<Self, B>
+    where
+        B: RangeBounds<f64> + Send + Sync,
+        Self: Sized,
+    {
+        AttrInBoundsReads::new(self, selector_expr, attr, new_attr, bounds)
+    }
+
     /// Set an attribute to true with some probability.
     ///
-    /// This is deterministic, even with multithreading.
+    /// This is deterministic, even with multithreading. The random numbers depend on the record
+    /// indexes of the reads, and [`Reads::split`] and [`Reads::split_all`] change the record index
+    /// `idx` of every read, including reads that are not selected, to `idx * MAX_SPLITS` or more.
     #[must_use]
     fn bernoulli(
         self,
@@ -322,6 +376,33 @@ pub trait Reads: Send + Sync {
     /// that is matched. If no pattern matches, then it will be set to false.
     /// Assuming pattern `AAAA` is matched, `seq1.*.some_extra_data1` will be set to `"all As"` and
     /// `seq1.*.some_extra_data2` will be set to `true`.
+    ///
+    /// Each pattern can also override the `match_type` of the operation, along with its
+    /// `max_mismatches` (for Hamming or edit distance), `identity`, and `overlap` parameters:
+    /// ```yaml
+    /// name: my_barcodes
+    /// patterns:
+    ///   - pattern: ACGTACGT
+    ///     max_mismatches: 1
+    ///   - pattern: ACGTACGTACGT
+    ///     match_type: HammingPrefix
+    ///     max_mismatches: 2
+    /// ```
+    /// Parameters that are not overridden are taken from the operation-level [`MatchType`].
+    /// A pattern's match type must be at least as anchored as the operation-level match type, and
+    /// its match is converted to the mappings of the operation-level match type. For example,
+    /// a prefix match with an `ExactSearch` operation will result in an empty mapping before
+    /// the match.
+    ///
+    /// Use [`MatchAnyReads::strand`] to also match the reverse complement of the patterns.
+    /// Use [`MatchAnyReads::iupac`] to treat IUPAC codes like `N` as degenerate bases.
+    /// Use [`MatchAnyReads::scores`] and [`MatchAnyReads::min_margin`] to detect reads where
+    /// multiple patterns match similarly well.
+    /// Use [`MatchAnyReads::edits`] to record the number of edits of the matched pattern.
+    ///
+    /// For `ExactSearch` and `ExactPrefix`, if all patterns are literal strings (they do not depend
+    /// on the read), then they are searched for at once with an Aho-Corasick automaton, which is
+    /// much faster for large sets of patterns.
     #[must_use]
     fn match_any(
         self,
@@ -343,6 +424,57 @@ pub trait Reads: Send + Sync {
         )
     }
 
+    /// Match any one of multiple patterns from a file in a mapping.
+    ///
+    /// This is like `match_any`, but the patterns are loaded with [`Patterns::from_file`], so
+    /// they can be in a plain list, a CSV or TSV table, or a FASTA file. `name` is the
+    /// attribute that is set to the matched pattern. For tables, the patterns are in the column
+    /// named `pattern_column`, and the other columns are stored as attributes.
+    ///
+    /// Example: `match_any_file(sel!(), tr!(seq1.* -> seq1.i7, seq1.rest), "i7", "samples.csv.gz", "i7", HammingPrefix(Count(8)))`.
+    /// With a sample sheet that has the columns `sample,i7,i5`, this sets `seq1.i7.i7`,
+    /// `seq1.i7.sample`, and `seq1.i7.i5` for the matched row.
+    #[must_use]
+    fn match_any_file(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        name: impl AsRef<str>,
+        file: impl AsRef<str>,
+        pattern_column: impl AsRef<str>,
+        match_type: MatchType,
+    ) -> MatchAnyReads<Self>
+    where
+        Self: Sized,
+    {
+        MatchAnyReads::new(
+            self,
+            selector_expr,
+            transform_expr,
+            Patterns::from_file(name, file, pattern_column)
+                .unwrap_or_else(|e| panic!("Error in loading patterns: {e}")),
+            match_type,
+        )
+    }
+
+    /// Match any one of multiple patterns in a mapping, with patterns that are already loaded.
+    ///
+    /// This is like `match_any`, but it can be used with any of the [`Patterns`] constructors,
+    /// like [`Patterns::from_table`] for choosing the column that contains the patterns.
+    #[must_use]
+    fn match_any_patterns(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        patterns: Patterns,
+        match_type: MatchType,
+    ) -> MatchAnyReads<Self>
+    where
+        Self: Sized,
+    {
+        MatchAnyReads::new(self, selector_expr, transform_expr, patterns, match_type)
+    }
+
     /// Match a pattern in a mapping.
     ///
     /// The pattern can be an arbitrary format expression, so you can use any existing mappings or
@@ -353,6 +485,9 @@ pub trait Reads: Send + Sync {
     ///
     /// Example `transform_expr` for local-alignment-based pattern matching:
     /// `tr!(seq1.* -> seq1.before, seq1.aligned, seq1.after)`.
+    ///
+    /// Use [`MatchAnyReads::strand`] to also match the reverse complement of the pattern.
+    /// Use [`MatchAnyReads::iupac`] to treat IUPAC codes like `N` as degenerate bases.
     #[must_use]
     fn match_one(
         self,
@@ -376,6 +511,105 @@ pub trait Reads: Send + Sync {
         )
     }
 
+    /// Match all non-overlapping occurrences of a pattern in a mapping, from left to right.
+    ///
+    /// The pattern can be an arbitrary format expression, like in `match_one`. Only
+    /// `ExactSearch`, `HammingSearch`, and `EditSearch` can be used.
+    ///
+    /// The transform expression must have one input mapping and three outputs: a label for the
+    /// occurrences, a label for the segments between them, and an attribute for the number of
+    /// occurrences.
+    ///
+    /// Example `transform_expr`: `tr!(seq1.* -> seq1.adapter, seq1.segment, seq1.*.num_adapters)`.
+    /// If there are two occurrences, then this will create mappings `seq1.adapter_0` and
+    /// `seq1.adapter_1` for the occurrences, mappings `seq1.segment_0`, `seq1.segment_1`, and
+    /// `seq1.segment_2` for everything before, between, and after the occurrences, and set
+    /// `seq1.*.num_adapters` to 2.
+    #[must_use]
+    fn match_all(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        pattern: impl AsRef<str>,
+        match_type: MatchType,
+    ) -> MatchAllReads<Self>
+    where
+        Self: Sized,
+    {
+        MatchAllReads::new(
+            self,
+            selector_expr,
+            transform_expr,
+            FormatExpr::new(pattern.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the match_all operation: {e}")
+            }),
+            match_type,
+        )
+    }
+
+    /// Match all non-overlapping occurrences of a regex pattern in a mapping, from left to right.
+    ///
+    /// Empty matches are ignored. The transform expression is the same as in `match_all`.
+    ///
+    /// Example `transform_expr`: `tr!(seq1.* -> seq1.linker, seq1.segment, seq1.*.num_linkers)`.
+    #[must_use]
+    fn match_regex_all(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        regex: impl AsRef<str>,
+    ) -> MatchAllReads<Self>
+    where
+        Self: Sized,
+    {
+        MatchAllReads::new_regex(self, selector_expr, transform_expr, regex.as_ref())
+    }
+
+    /// Match a linked pair of 5' and 3' adapters in a mapping.
+    ///
+    /// The 3' adapter is only matched after the end of the 5' adapter. The 5' adapter can use prefix
+    /// match types to anchor it to the start of the mapping, or search match types to find it anywhere.
+    /// Similarly, the 3' adapter can use suffix or search match types. The adapters can be arbitrary
+    /// format expressions, like in `match_one`.
+    ///
+    /// By default, both adapters must be found. Use [`MatchLinkedReads::required`] to make either
+    /// adapter optional and [`MatchLinkedReads::found`] to record which adapters are found.
+    ///
+    /// The transform expression must have one input mapping and five output mappings: everything
+    /// before the 5' adapter, the 5' adapter, the insert between the adapters, the 3' adapter, and
+    /// everything after the 3' adapter.
+    ///
+    /// Example `transform_expr`: `tr!(seq1.* -> _, seq1.adapter5, seq1.insert, seq1.adapter3, _)`.
+    #[must_use]
+    fn match_linked(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        adapter5: impl AsRef<str>,
+        match_type5: MatchType,
+        adapter3: impl AsRef<str>,
+        match_type3: MatchType,
+    ) -> MatchLinkedReads<Self>
+    where
+        Self: Sized,
+    {
+        let parse = |adapter: &str| {
+            FormatExpr::new(adapter.as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the match_linked operation: {e}")
+            })
+        };
+
+        MatchLinkedReads::new(
+            self,
+            selector_expr,
+            transform_expr,
+            parse(adapter5.as_ref()),
+            match_type5,
+            parse(adapter3.as_ref()),
+            match_type3,
+        )
+    }
+
     /// Match repeated characters from the left or right end of a mapping.
     ///
     /// The transform expression must have one input mapping and two output mappings.
@@ -396,6 +630,104 @@ pub trait Reads: Send + Sync {
         MatchPolyXReads::new(self, selector_expr, transform_expr, x as u8, end, identity)
     }
 
+    /// Match low quality bases from the left or right end of a mapping.
+    ///
+    /// This uses the same algorithm as the `-q` option in cutadapt and BWA. Bases are scored with
+    /// their phred quality scores relative to `cutoff`, where `phred_offset` is the ASCII offset
+    /// of the quality scores (usually 33).
+    ///
+    /// The transform expression must have one input mapping and two output mappings.
+    /// The output mappings are always created, so the low quality mapping may be empty.
+    /// Reads without quality scores are treated as high quality.
+    ///
+    /// Example `transform_expr` when trimming from the right end:
+    /// `tr!(seq1.* -> seq1.good, seq1.low_qual)`.
+    #[must_use]
+    fn match_quality(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        cutoff: u8,
+        end: End,
+        phred_offset: u8,
+    ) -> MatchQualityReads<Self>
+    where
+        Self: Sized,
+    {
+        MatchQualityReads::new(
+            self,
+            selector_expr,
+            transform_expr,
+            cutoff,
+            end,
+            phred_offset,
+        )
+    }
+
+    /// Compute a quality score statistic for a mapping.
+    ///
+    /// The transform expression must have one input mapping and one output attribute.
+    /// `phred_offset` is the ASCII offset of the quality scores (usually 33).
+    /// The attribute is set to `false` for reads without quality scores.
+    ///
+    /// Example `transform_expr`: `tr!(seq1.umi -> seq1.umi.ee)`.
+    /// This will set `seq1.umi.ee` to the specified statistic (e.g., `QualStat::ExpectedErrors`),
+    /// which can be checked with `attr_in_bounds`.
+    #[must_use]
+    fn quality_stats(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        stat: QualStat,
+        phred_offset: u8,
+    ) -> QualityStatsReads<Self>
+    where
+        Self: Sized,
+    {
+        QualityStatsReads::new(self, selector_expr, transform_expr, stat, phred_offset)
+    }
+
+    /// Correct a barcode to the closest barcode in a whitelist.
+    ///
+    /// The whitelist file must contain one barcode per line (only the first column is used) and
+    /// it can be gzipped. All sequences within `max_dist` of a whitelist barcode are precomputed,
+    /// so this is much faster than `match_any` for large whitelists. Note that the number of
+    /// sequences grows quickly with `max_dist`, especially for edit distance.
+    ///
+    /// The transform expression must have one input mapping and three output attributes.
+    ///
+    /// Example `transform_expr`: `tr!(seq1.bc -> seq1.bc.corrected, seq1.bc.dist, seq1.bc.ambiguous)`.
+    /// If there is a unique closest whitelist barcode, then `seq1.bc.corrected` will be set to it,
+    /// `seq1.bc.dist` will be set to the distance, and `seq1.bc.ambiguous` will be set to false.
+    /// If there are multiple closest whitelist barcodes, then `seq1.bc.corrected` will be set to
+    /// false and `seq1.bc.ambiguous` will be set to true.
+    /// If no whitelist barcode is within `max_dist`, then all three attributes will be set to false.
+    ///
+    /// Use [`CorrectBarcodeReads::posterior`] to correct barcodes using quality scores, which is
+    /// only supported with [`DistType::Hamming`].
+    #[must_use]
+    fn correct_barcode(
+        self,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        whitelist_file: impl AsRef<str>,
+        max_dist: usize,
+        dist_type: DistType,
+    ) -> CorrectBarcodeReads<Self>
+    where
+        Self: Sized,
+    {
+        CorrectBarcodeReads::new(
+            self,
+            selector_expr,
+            transform_expr,
+            whitelist_file.as_ref(),
+            max_dist,
+            dist_type,
+        )
+        .unwrap_or_else(|e| panic!("Error in loading barcode whitelist: {e}"))
+    }
+
     /// Output reads to a specified file.
     ///
     /// The file path is a format expression.
@@ -447,6 +779,274 @@ pub trait Reads: Send + Sync {
         )
     }
 
+    /// Output reads with any number of sequence segments to the specified files.
+    ///
+    /// The file paths are format expressions.
+    ///
+    /// The `i`-th file (starting from 1) receives `name{i}` and `seq{i}`.
+    /// The records will be interleaved if some files are the same.
+    #[must_use]
+    fn collect_fastqn(
+        self,
+        selector_expr: SelectorExpr,
+        file_exprs: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> CollectFastqReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastqReads::new_n(
+            self,
+            selector_expr,
+            file_exprs
+                .into_iter()
+                .map(|file_expr| {
+                    FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                        panic!(
+                            "Error in parsing format expression for the collect_fastqn operation: {e}"
+                        )
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Output index 1 sequences to a specified file.
+    ///
+    /// The file path is a format expression.
+    ///
+    /// Each fastq record uses `name1` as its name and `index1` as its sequence.
+    #[must_use]
+    fn collect_fastq_index1(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr: impl AsRef<str>,
+    ) -> CollectFastqReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastqReads::new_index(
+            self,
+            selector_expr,
+            StrType::Index(1),
+            FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!(
+                    "Error in parsing format expression for the collect_fastq_index1 operation: {e}"
+                )
+            }),
+        )
+    }
+
+    /// Output index 2 sequences to a specified file.
+    ///
+    /// The file path is a format expression.
+    ///
+    /// Each fastq record uses `name1` as its name and `index2` as its sequence.
+    #[must_use]
+    fn collect_fastq_index2(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr: impl AsRef<str>,
+    ) -> CollectFastqReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastqReads::new_index(
+            self,
+            selector_expr,
+            StrType::Index(2),
+            FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!(
+                    "Error in parsing format expression for the collect_fastq_index2 operation: {e}"
+                )
+            }),
+        )
+    }
+
+    /// Output reads to a specified fasta file.
+    ///
+    /// The file path is a format expression.
+    ///
+    /// Only read 1 is written out. If `line_width` is specified, then sequences are wrapped
+    /// across multiple lines.
+    #[must_use]
+    fn collect_fasta1(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr: impl AsRef<str>,
+        line_width: Option<usize>,
+    ) -> CollectFastaReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastaReads::new1(
+            self,
+            selector_expr,
+            FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the collect_fasta1 operation: {e}")
+            }),
+            line_width,
+        )
+    }
+
+    /// Output paired-end reads to the specified fasta files.
+    ///
+    /// The file paths are format expressions.
+    ///
+    /// Read 1 is written to `file_expr1` and read 2 is written to `file_expr2`.
+    /// The reads will be interleaved if the files are the same.
+    /// If `line_width` is specified, then sequences are wrapped across multiple lines.
+    #[must_use]
+    fn collect_fasta2(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr1: impl AsRef<str>,
+        file_expr2: impl AsRef<str>,
+        line_width: Option<usize>,
+    ) -> CollectFastaReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastaReads::new2(
+            self,
+            selector_expr,
+            FormatExpr::new(file_expr1.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the collect_fasta2 operation: {e}")
+            }),
+            FormatExpr::new(file_expr2.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the collect_fasta2 operation: {e}")
+            }),
+            line_width,
+        )
+    }
+
+    /// Output reads with any number of sequence segments to the specified fasta files.
+    ///
+    /// The file paths are format expressions.
+    ///
+    /// The `i`-th file (starting from 1) receives `name{i}` and `seq{i}`.
+    /// The records will be interleaved if some files are the same.
+    /// If `line_width` is specified, then sequences are wrapped across multiple lines.
+    #[must_use]
+    fn collect_fastan(
+        self,
+        selector_expr: SelectorExpr,
+        file_exprs: impl IntoIterator<Item = impl AsRef<str>>,
+        line_width: Option<usize>,
+    ) -> CollectFastaReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectFastaReads::new_n(
+            self,
+            selector_expr,
+            file_exprs
+                .into_iter()
+                .map(|file_expr| {
+                    FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                        panic!(
+                            "Error in parsing format expression for the collect_fastan operation: {e}"
+                        )
+                    })
+                })
+                .collect(),
+            line_width,
+        )
+    }
+
+    /// Output reads as unaligned SAM records to a specified file.
+    ///
+    /// The file path is a format expression. The file will be gzip compressed if it ends with
+    /// `.gz`.
+    ///
+    /// Reads that contain `seq2` are written as two adjacent paired records. Otherwise, only read 1
+    /// is written.
+    ///
+    /// Each [`SamTag`] is written for all records, unless its label or attribute is not in the read.
+    /// For example, `[SamTag::new("UB", label!(seq1.umi)), SamTag::new("CB", label!(seq1.bc)),
+    /// SamTag::qual("QX", label!(seq1.bc))]` writes the UMI, the cell barcode, and the cell barcode
+    /// quality scores.
+    #[must_use]
+    fn collect_sam(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr: impl AsRef<str>,
+        tags: impl Into<Vec<SamTag>>,
+    ) -> CollectSamReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectSamReads::new(
+            self,
+            selector_expr,
+            FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the collect_sam operation: {e}")
+            }),
+            tags.into(),
+            false,
+        )
+    }
+
+    /// Output reads as unaligned BAM records to a specified file.
+    ///
+    /// This is the same as [`collect_sam()`](Reads::collect_sam), except the output is BAM.
+    #[must_use]
+    fn collect_bam(
+        self,
+        selector_expr: SelectorExpr,
+        file_expr: impl AsRef<str>,
+        tags: impl Into<Vec<SamTag>>,
+    ) -> CollectSamReads<Self>
+    where
+        Self: Sized,
+    {
+        CollectSamReads::new(
+            self,
+            selector_expr,
+            FormatExpr::new(file_expr.as_ref().as_bytes()).unwrap_or_else(|e| {
+                panic!("Error in parsing format expression for the collect_bam operation: {e}")
+            }),
+            tags.into(),
+            true,
+        )
+    }
+
+    /// Split each selected read into one new read for each of the specified labels.
+    ///
+    /// In each new read, the string of the label will only contain the substring of the label's
+    /// mapping, along with any mappings completely inside of it. All other strings in the read are
+    /// copied. Labels with empty mappings do not produce new reads. Reads that are not selected are
+    /// kept as is.
+    ///
+    /// The new reads keep the origin of the original read. The record index of the new read for
+    /// the `i`-th label is `idx * MAX_SPLITS + i`, where `idx` is the record index of the original
+    /// read, so `bernoulli` and `take` are still deterministic. Reads that are not selected get
+    /// the record index `idx * MAX_SPLITS`.
+    /// An error is returned if a new record index does not fit in a `usize`.
+    ///
+    /// Example `labels`: `[label!(seq1.left), label!(seq1.right)]`.
+    #[must_use]
+    fn split(self, selector_expr: SelectorExpr, labels: impl Into<Vec<Label>>) -> SplitReads<Self>
+    where
+        Self: Sized,
+    {
+        SplitReads::new(self, selector_expr, labels.into())
+    }
+
+    /// Split each selected read into one new read for each indexed mapping of a label.
+    ///
+    /// This is like `split`, but it uses the mappings `label_0`, `label_1`, etc., until a
+    /// mapping does not exist. This can be used to split on the segments created by `match_all`.
+    /// An error is returned if a read has more than [`MAX_SPLITS`] indexed mappings.
+    ///
+    /// Example `label`: `label!(seq1.segment)`.
+    #[must_use]
+    fn split_all(self, selector_expr: SelectorExpr, label: Label) -> SplitReads<Self>
+    where
+        Self: Sized,
+    {
+        SplitReads::new_indexed(self, selector_expr, label)
+    }
+
     /// Retain only the reads that are selected and discard the rest.
     #[must_use]
     fn retain(self, selector_expr: SelectorExpr) -> RetainReads<Self>
@@ -457,6 +1057,10 @@ pub trait Reads: Send + Sync {
     }
 
     /// Take only the reads that have a record index inside the bounds.
+    ///
+    /// [`Reads::split`] and [`Reads::split_all`] change the record index `idx` of every read,
+    /// including reads that are not selected, to `idx * MAX_SPLITS` or more, so the bounds must
+    /// be scaled by [`MAX_SPLITS`] after splitting.
     #[must_use]
     fn take<B>(self, bounds: B) -> TakeReads<Self, B>
     where
@@ -643,6 +1247,9 @@ pub use Threshold::*;
 /// identity computation. This is important for local alignment, where the start and end of the
 /// pattern can be excluded from the alignment, and prefix/suffix alignment, where the start/end
 /// of the pattern can be excluded from the alignment (prefix/suffix "overhang").
+///
+/// For edit-distance-based algorithms, the entire pattern must be matched and the number of
+/// matching bases is `pattern_length - edits`.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MatchType {
     /// Exact match.
@@ -690,6 +1297,29 @@ pub enum MatchType {
     /// A match will result in three new mappings: everything before the match, the matching
     /// region, and everything after the match.
     HammingSearch(Threshold),
+    /// Edit-distance-based prefix matching.
+    ///
+    /// Threshold is for the max number of edits (substitutions, insertions, and deletions).
+    ///
+    /// A match will result in two new mappings: the matched prefix and the rest of the
+    /// string.
+    EditPrefix(Threshold),
+    /// Edit-distance-based suffix matching.
+    ///
+    /// Threshold is for the max number of edits (substitutions, insertions, and deletions).
+    ///
+    /// A match will result in two new mappings: the rest of the string and the matched
+    /// suffix.
+    EditSuffix(Threshold),
+    /// Edit-distance-based searching.
+    ///
+    /// Threshold is for the max number of edits (substitutions, insertions, and deletions).
+    /// If there are multiple matches with the fewest edits, then the leftmost shortest match is
+    /// used.
+    ///
+    /// A match will result in three new mappings: everything before the match, the matching
+    /// region, and everything after the match.
+    EditSearch(Threshold),
     /// Global-alignment-based matching.
     ///
     /// Threshold is for the sequence identity.
@@ -722,11 +1352,72 @@ impl MatchType {
             | ExactSuffix
             | HammingPrefix(_)
             | HammingSuffix(_)
+            | EditPrefix(_)
+            | EditSuffix(_)
             | PrefixAln { .. }
             | SuffixAln { .. } => 2,
-            ExactSearch | HammingSearch(_) | LocalAln { .. } => 3,
+            ExactSearch | HammingSearch(_) | EditSearch(_) | LocalAln { .. } => 3,
+        }
+    }
+
+    /// Whether matches are anchored to the start and end of the string.
+    fn anchors(&self) -> (bool, bool) {
+        use MatchType::*;
+        match self {
+            Exact | Hamming(_) | GlobalAln(_) => (true, true),
+            ExactPrefix | HammingPrefix(_) | EditPrefix(_) | PrefixAln { .. } => (true, false),
+            ExactSuffix | HammingSuffix(_) | EditSuffix(_) | SuffixAln { .. } => (false, true),
+            ExactSearch | HammingSearch(_) | EditSearch(_) | LocalAln { .. } => (false, false),
         }
     }
+
+    /// Whether every match of this match type can be represented by the mappings of `other`.
+    pub(crate) fn fits(&self, other: &MatchType) -> bool {
+        let (start, end) = self.anchors();
+        let (other_start, other_end) = other.anchors();
+        (start || !other_start) && (end || !other_end)
+    }
+
+    /// Convert the cut positions of a match into the start and end indexes of the matched region.
+    pub(crate) fn region(
+        &self,
+        string_len: usize,
+        cut_pos1: usize,
+        cut_pos2: usize,
+    ) -> (usize, usize) {
+        match self.anchors() {
+            (true, true) => (0, string_len),
+            (true, false) => (0, cut_pos1),
+            (false, true) => (cut_pos1, string_len),
+            (false, false) => (cut_pos1, cut_pos2),
+        }
+    }
+
+    /// Convert the start and end indexes of a matched region into cut positions.
+    pub(crate) fn cut_positions(
+        &self,
+        string_len: usize,
+        start: usize,
+        end: usize,
+    ) -> (usize, usize) {
+        match self.anchors() {
+            (true, true) => (string_len, 0),
+            (true, false) => (end, 0),
+            (false, true) => (start, 0),
+            (false, false) => (start, end),
+        }
+    }
+}
+
+/// Orientations of patterns to match.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strand {
+    /// Only match patterns as is.
+    Forward,
+    /// Only match the reverse complement of patterns.
+    RevComp,
+    /// Match both orientations of patterns.
+    Both,
 }
 
 /// Either a count or a fraction.
@@ -748,3 +1439,38 @@ impl Threshold {
         }
     }
 }
+
+/// Quality score statistics.
+///
+/// Quality scores are the phred scores after subtracting the phred offset.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QualStat {
+    /// Mean quality score, as a float.
+    Mean,
+    /// Minimum quality score, as a uint.
+    Min,
+    /// Expected number of errors (sum of `10^(-Q/10)`), as a float.
+    ExpectedErrors,
+    /// Fraction of quality scores that are below a cutoff, as a float.
+    FracBelow(u8),
+    /// Minimum mean quality score across all sliding windows of a certain size, as a float.
+    ///
+    /// This is similar to Trimmomatic's `SLIDINGWINDOW`, but for filtering instead of trimming.
+    /// If the mapping is shorter than the window, then the mean of the entire mapping is used.
+    MinWindowMean(usize),
+}
+
+/// Types of distances between sequences.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DistType {
+    /// Number of substitutions.
+    Hamming,
+    /// Number of substitutions, insertions, and deletions.
+    Edit,
+}
+
+/// The maximum number of reads that a single read can be split into.
+///
+/// The record index of the `i`-th read split from a read with record index `idx` is
+/// `idx * MAX_SPLITS + i`.
+pub const MAX_SPLITS: usize = 1 << 16;
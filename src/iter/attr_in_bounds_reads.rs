@@ -0,0 +1,79 @@
+use std::ops::RangeBounds;
+
+use crate::iter::*;
+
+pub struct AttrInBoundsReads<R: Reads, B: RangeBounds<f64> + Send + Sync> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    attr: Attr,
+    new_attr: Attr,
+    bounds: B,
+}
+
+impl<R: Reads, B: RangeBounds<f64> + Send + Sync> AttrInBoundsReads<R, B> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        attr: Attr,
+        new_attr: Attr,
+        bounds: B,
+    ) -> Self {
+        Self {
+            reads,
+            selector_expr,
+            attr,
+            new_attr,
+            bounds,
+        }
+    }
+}
+
+impl<R: Reads, B: RangeBounds<f64> + Send + Sync> Reads for AttrInBoundsReads<R, B> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "checking attribute in bounds",
+                })?)
+            {
+                continue;
+            }
+
+            let in_bounds = match *read
+                .data(self.attr.str_type, self.attr.label, self.attr.attr)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "checking attribute in bounds",
+                })? {
+                Data::UInt(x) => self.bounds.contains(&(x as f64)),
+                Data::Int(x) => self.bounds.contains(&(x as f64)),
+                Data::Float(x) => self.bounds.contains(&x),
+                // e.g., `quality_stats` sets `false` for reads without quality scores
+                Data::Bool(_) | Data::Bytes(_) => false,
+            };
+
+            // panic to make borrow checker happy
+            *read
+                .data_mut(
+                    self.new_attr.str_type,
+                    self.new_attr.label,
+                    self.new_attr.attr,
+                )
+                .unwrap_or_else(|e| panic!("Error checking attribute in bounds: {e}")) =
+                Data::Bool(in_bounds);
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
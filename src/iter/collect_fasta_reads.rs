@@ -0,0 +1,81 @@
+use std::io::{self, Write};
+
+use crate::fasta::*;
+use crate::iter::*;
+
+pub type CollectFastaReads<R> = CollectReads<R, FastaWriter>;
+
+/// Writes fasta records.
+pub struct FastaWriter {
+    // name type and sequence type of the record for each file
+    str_types: Vec<(StrType, StrType)>,
+    line_width: Option<usize>,
+}
+
+impl RecordWriter for FastaWriter {
+    type Record<'a> = (&'a [u8], &'a [u8]);
+
+    fn context(&self) -> &'static str {
+        "collecting into fasta file(s)"
+    }
+
+    fn record<'a>(
+        &self,
+        read: &'a Read,
+        i: usize,
+    ) -> std::result::Result<(&'a [u8], &'a [u8]), NameError> {
+        let (name_type, seq_type) = self.str_types[i];
+        read.to_fasta(name_type, seq_type)
+    }
+
+    fn write(&self, writer: &mut (dyn Write + Send), record: (&[u8], &[u8])) -> io::Result<()> {
+        write_fasta_record(writer, record, self.line_width)
+    }
+}
+
+impl<R: Reads> CollectFastaReads<R> {
+    pub fn new1(
+        reads: R,
+        selector_expr: SelectorExpr,
+        file_expr: FormatExpr,
+        line_width: Option<usize>,
+    ) -> Self {
+        Self::new_n(reads, selector_expr, vec![file_expr], line_width)
+    }
+
+    pub fn new2(
+        reads: R,
+        selector_expr: SelectorExpr,
+        file_expr1: FormatExpr,
+        file_expr2: FormatExpr,
+        line_width: Option<usize>,
+    ) -> Self {
+        Self::new_n(
+            reads,
+            selector_expr,
+            vec![file_expr1, file_expr2],
+            line_width,
+        )
+    }
+
+    /// Write each sequence segment `seq{i}` (starting from 1) to the `i`-th file.
+    pub fn new_n(
+        reads: R,
+        selector_expr: SelectorExpr,
+        file_exprs: Vec<FormatExpr>,
+        line_width: Option<usize>,
+    ) -> Self {
+        let str_types = (1..=file_exprs.len())
+            .map(|i| (StrType::Name(i), StrType::Seq(i)))
+            .collect();
+        CollectReads::with_writer(
+            reads,
+            selector_expr,
+            file_exprs,
+            FastaWriter {
+                str_types,
+                line_width,
+            },
+        )
+    }
+}
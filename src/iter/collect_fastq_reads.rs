@@ -1,31 +1,40 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::sync::{Arc, Mutex};
-
-use rustc_hash::FxHashMap;
-
-use flate2::{write::GzEncoder, Compression};
+use std::io::{self, Write};
 
 use crate::fastq::*;
 use crate::iter::*;
 
-pub struct CollectFastqReads<R: Reads> {
-    reads: R,
-    selector_expr: SelectorExpr,
-    file_expr1: FormatExpr,
-    file_expr2: Option<FormatExpr>,
-    file_writers: Mutex<FxHashMap<Vec<u8>, Arc<Mutex<dyn Write + Send>>>>,
+pub type CollectFastqReads<R> = CollectReads<R, FastqWriter>;
+
+/// Writes fastq records.
+pub struct FastqWriter {
+    // name type and sequence type of the record for each file
+    str_types: Vec<(StrType, StrType)>,
+}
+
+impl RecordWriter for FastqWriter {
+    type Record<'a> = FastqRecord<'a>;
+
+    fn context(&self) -> &'static str {
+        "collecting into fastq file(s)"
+    }
+
+    fn record<'a>(
+        &self,
+        read: &'a Read,
+        i: usize,
+    ) -> std::result::Result<FastqRecord<'a>, NameError> {
+        let (name_type, seq_type) = self.str_types[i];
+        read.to_fastq(name_type, seq_type)
+    }
+
+    fn write(&self, writer: &mut (dyn Write + Send), record: FastqRecord) -> io::Result<()> {
+        write_fastq_record(writer, record)
+    }
 }
 
 impl<R: Reads> CollectFastqReads<R> {
     pub fn new1(reads: R, selector_expr: SelectorExpr, file_expr: FormatExpr) -> Self {
-        Self {
-            reads,
-            selector_expr,
-            file_expr1: file_expr,
-            file_expr2: None,
-            file_writers: Mutex::new(FxHashMap::default()),
-        }
+        Self::new_n(reads, selector_expr, vec![file_expr])
     }
 
     pub fn new2(
@@ -34,134 +43,31 @@ impl<R: Reads> CollectFastqReads<R> {
         file_expr1: FormatExpr,
         file_expr2: FormatExpr,
     ) -> Self {
-        Self {
-            reads,
-            selector_expr,
-            file_expr1,
-            file_expr2: Some(file_expr2),
-            file_writers: Mutex::new(FxHashMap::default()),
-        }
+        Self::new_n(reads, selector_expr, vec![file_expr1, file_expr2])
     }
-}
 
-impl<R: Reads> Reads for CollectFastqReads<R> {
-    fn next_chunk(&self) -> Result<Vec<Read>> {
-        let reads = self.reads.next_chunk()?;
-        let mut locked_writers = Vec::with_capacity(reads.len());
-
-        // get the corresponding file writer for each read first so writing to different files can be parallelized
-        // TODO: use concurrent hashmap?
-        {
-            let mut file_writers = self.file_writers.lock().unwrap();
-
-            let mut get_writer = |file_name: &[u8]| -> std::io::Result<()> {
-                use std::collections::hash_map::Entry::*;
-                match file_writers.entry(file_name.to_owned()) {
-                    Occupied(e) => {
-                        locked_writers.push(Arc::clone(e.get()));
-                    }
-                    Vacant(e) => {
-                        // need to create the output file
-                        let file_path = std::str::from_utf8(file_name).unwrap();
-
-                        if let Some(parent) = std::path::Path::new(file_path).parent() {
-                            std::fs::create_dir_all(parent)?;
-                        }
-
-                        let writer: Arc<Mutex<dyn Write + Send>> = if file_path.ends_with(".gz") {
-                            Arc::new(Mutex::new(BufWriter::new(GzEncoder::new(
-                                File::create(file_path)?,
-                                Compression::default(),
-                            ))))
-                        } else {
-                            Arc::new(Mutex::new(BufWriter::new(File::create(file_path)?)))
-                        };
-                        locked_writers.push(Arc::clone(e.insert(writer)));
-                    }
-                }
-
-                Ok(())
-            };
-
-            for read in reads.iter() {
-                if !(self
-                    .selector_expr
-                    .matches(read)
-                    .map_err(|e| Error::NameError {
-                        source: e,
-                        read: read.clone(),
-                        context: "collecting into fastq file(s)",
-                    })?)
-                {
-                    continue;
-                }
-
-                let file_name =
-                    self.file_expr1
-                        .format(read, false)
-                        .map_err(|e| Error::NameError {
-                            source: e,
-                            read: read.clone(),
-                            context: "collecting into fastq file(s)",
-                        })?;
-                get_writer(&file_name).map_err(|e| Error::FileIo {
-                    file: utf8(&file_name),
-                    source: Box::new(e),
-                })?;
-
-                if let Some(file_expr2) = &self.file_expr2 {
-                    let file_name =
-                        file_expr2
-                            .format(read, false)
-                            .map_err(|e| Error::NameError {
-                                source: e,
-                                read: read.clone(),
-                                context: "collecting into fastq file(s)",
-                            })?;
-                    get_writer(&file_name).map_err(|e| Error::FileIo {
-                        file: utf8(&file_name),
-                        source: Box::new(e),
-                    })?;
-                }
-            }
-        }
-
-        if self.file_expr2.is_some() {
-            for (locked_writer, read) in locked_writers.chunks(2).zip(
-                reads
-                    .iter()
-                    .filter(|r| self.selector_expr.matches(r).unwrap()),
-            ) {
-                let (record1, record2) = read.to_fastq2().map_err(|e| Error::NameError {
-                    source: e,
-                    read: read.clone(),
-                    context: "collecting into fastq file(s)",
-                })?;
-                // interleave records if the same file is specified twice
-                {
-                    let mut writer1 = locked_writer[0].lock().unwrap();
-                    write_fastq_record(&mut *writer1, record1);
-                }
-                {
-                    let mut writer2 = locked_writer[1].lock().unwrap();
-                    write_fastq_record(&mut *writer2, record2);
-                }
-            }
-        } else {
-            for (locked_writer, read) in locked_writers.into_iter().zip(
-                reads
-                    .iter()
-                    .filter(|r| self.selector_expr.matches(r).unwrap()),
-            ) {
-                let mut writer = locked_writer.lock().unwrap();
-                write_fastq_record(&mut *writer, read.to_fastq1());
-            }
-        }
-
-        Ok(reads)
+    /// Write each sequence segment `seq{i}` (starting from 1) to the `i`-th file.
+    pub fn new_n(reads: R, selector_expr: SelectorExpr, file_exprs: Vec<FormatExpr>) -> Self {
+        let str_types = (1..=file_exprs.len())
+            .map(|i| (StrType::Name(i), StrType::Seq(i)))
+            .collect();
+        CollectReads::with_writer(reads, selector_expr, file_exprs, FastqWriter { str_types })
     }
 
-    fn finish(&mut self) -> Result<()> {
-        self.reads.finish()
+    /// Write an index string as a fastq record, using `name1` as the record name.
+    pub fn new_index(
+        reads: R,
+        selector_expr: SelectorExpr,
+        index_type: StrType,
+        file_expr: FormatExpr,
+    ) -> Self {
+        CollectReads::with_writer(
+            reads,
+            selector_expr,
+            vec![file_expr],
+            FastqWriter {
+                str_types: vec![(StrType::Name(1), index_type)],
+            },
+        )
     }
 }
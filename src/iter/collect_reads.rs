@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use rustc_hash::FxHashMap;
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::iter::*;
+
+/// Output format of the records written by [`CollectReads`].
+pub trait RecordWriter: Send + Sync {
+    /// Record that is extracted from a read before locking its output file.
+    type Record<'a>;
+
+    /// Description of the operation for error messages.
+    fn context(&self) -> &'static str;
+
+    /// Get the record of a read that is written to the file of the `i`-th file expression.
+    fn record<'a>(
+        &self,
+        read: &'a Read,
+        i: usize,
+    ) -> std::result::Result<Self::Record<'a>, NameError>;
+
+    fn write(&self, writer: &mut (dyn Write + Send), record: Self::Record<'_>) -> io::Result<()>;
+
+    /// Create a new output file and write its header, if any.
+    ///
+    /// By default, the file is gzip compressed if it ends with `.gz`.
+    fn create(&self, file_path: &str) -> io::Result<Box<dyn Write + Send>> {
+        create_file(file_path)
+    }
+}
+
+/// Create an output file that is gzip compressed if it ends with `.gz`.
+pub fn create_file(file_path: &str) -> io::Result<Box<dyn Write + Send>> {
+    if file_path.ends_with(".gz") {
+        Ok(Box::new(BufWriter::new(GzEncoder::new(
+            File::create(file_path)?,
+            Compression::default(),
+        ))))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(file_path)?)))
+    }
+}
+
+/// Write each selected read to the files of one or more format expressions.
+pub struct CollectReads<R: Reads, W: RecordWriter> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    file_exprs: Vec<FormatExpr>,
+    record_writer: W,
+    file_writers: Mutex<FxHashMap<Vec<u8>, Arc<OutputFile>>>,
+}
+
+struct OutputFile {
+    name: Vec<u8>,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl<R: Reads, W: RecordWriter> CollectReads<R, W> {
+    pub fn with_writer(
+        reads: R,
+        selector_expr: SelectorExpr,
+        file_exprs: Vec<FormatExpr>,
+        record_writer: W,
+    ) -> Self {
+        assert!(
+            !file_exprs.is_empty(),
+            "Expected at least one file when {}",
+            record_writer.context()
+        );
+
+        Self {
+            reads,
+            selector_expr,
+            file_exprs,
+            record_writer,
+            file_writers: Mutex::new(FxHashMap::default()),
+        }
+    }
+}
+
+impl<R: Reads, W: RecordWriter> Reads for CollectReads<R, W> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let reads = self.reads.next_chunk()?;
+        let context = self.record_writer.context();
+        let mut locked_writers = Vec::with_capacity(reads.len());
+
+        // get the corresponding file writer for each read first so writing to different files can be parallelized
+        // TODO: use concurrent hashmap?
+        {
+            let mut file_writers = self.file_writers.lock().unwrap();
+
+            let mut get_writer = |file_name: &[u8]| -> std::io::Result<()> {
+                use std::collections::hash_map::Entry::*;
+                match file_writers.entry(file_name.to_owned()) {
+                    Occupied(e) => {
+                        locked_writers.push(Arc::clone(e.get()));
+                    }
+                    Vacant(e) => {
+                        // need to create the output file
+                        let file_path = std::str::from_utf8(file_name).unwrap();
+
+                        if let Some(parent) = std::path::Path::new(file_path).parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+
+                        let writer = Arc::new(OutputFile {
+                            name: file_name.to_owned(),
+                            writer: Mutex::new(self.record_writer.create(file_path)?),
+                        });
+                        locked_writers.push(Arc::clone(e.insert(writer)));
+                    }
+                }
+
+                Ok(())
+            };
+
+            for read in reads.iter() {
+                if !(self
+                    .selector_expr
+                    .matches(read)
+                    .map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context,
+                    })?)
+                {
+                    continue;
+                }
+
+                for file_expr in &self.file_exprs {
+                    let file_name =
+                        file_expr
+                            .format(read, false)
+                            .map_err(|e| Error::NameError {
+                                source: e,
+                                read: read.clone(),
+                                context,
+                            })?;
+                    get_writer(&file_name).map_err(|e| Error::FileIo {
+                        file: utf8(&file_name),
+                        source: Box::new(e),
+                    })?;
+                }
+            }
+        }
+
+        for (locked_writer, read) in locked_writers.chunks(self.file_exprs.len()).zip(
+            reads
+                .iter()
+                .filter(|r| self.selector_expr.matches(r).unwrap()),
+        ) {
+            // interleave records if the same file is specified multiple times
+            for (i, output_file) in locked_writer.iter().enumerate() {
+                let record = self
+                    .record_writer
+                    .record(read, i)
+                    .map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context,
+                    })?;
+                let mut writer = output_file.writer.lock().unwrap();
+                self.record_writer
+                    .write(&mut **writer, record)
+                    .map_err(|e| Error::FileIo {
+                        file: utf8(&output_file.name),
+                        source: Box::new(e),
+                    })?;
+            }
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::iter::*;
+use crate::sam::*;
+
+pub type CollectSamReads<R> = CollectReads<R, SamWriter>;
+
+/// Writes unaligned SAM or BAM records.
+pub struct SamWriter {
+    tags: Vec<SamTag>,
+    bam: bool,
+}
+
+/// Flag, sequence, and quality scores of a mate.
+type SamMate<'a> = (u16, &'a [u8], Option<&'a [u8]>);
+
+/// The unaligned records of a read, with one record for each mate.
+pub struct SamRecords<'a> {
+    qname: &'a [u8],
+    mates: Vec<SamMate<'a>>,
+    tags: Vec<([u8; 2], Data)>,
+}
+
+impl RecordWriter for SamWriter {
+    type Record<'a> = SamRecords<'a>;
+
+    fn context(&self) -> &'static str {
+        if self.bam {
+            "collecting into bam file(s)"
+        } else {
+            "collecting into sam file(s)"
+        }
+    }
+
+    fn record<'a>(
+        &self,
+        read: &'a Read,
+        _i: usize,
+    ) -> std::result::Result<SamRecords<'a>, NameError> {
+        let mut tags = Vec::with_capacity(self.tags.len());
+
+        for tag in &self.tags {
+            if let Some(data) = tag.data(read)? {
+                tags.push((tag.tag(), data));
+            }
+        }
+
+        let (name1, seq1, qual1) = read.to_sam(StrType::Name(1), StrType::Seq(1))?;
+
+        // mates are written next to each other with the same query name
+        let mates = match read.to_sam(StrType::Name(2), StrType::Seq(2)) {
+            Ok((_, seq2, qual2)) => vec![
+                (sam_flag(true, 1), seq1, qual1),
+                (sam_flag(true, 2), seq2, qual2),
+            ],
+            Err(_) => vec![(sam_flag(false, 1), seq1, qual1)],
+        };
+
+        Ok(SamRecords {
+            qname: sam_qname(name1),
+            mates,
+            tags,
+        })
+    }
+
+    fn write(&self, writer: &mut (dyn Write + Send), record: SamRecords) -> io::Result<()> {
+        let write_record = if self.bam {
+            write_bam_record
+        } else {
+            write_sam_record
+        };
+
+        for (flag, seq, qual) in record.mates {
+            write_record(writer, record.qname, flag, seq, qual, &record.tags)?;
+        }
+
+        Ok(())
+    }
+
+    fn create(&self, file_path: &str) -> io::Result<Box<dyn Write + Send>> {
+        if self.bam {
+            let mut w = BgzfWriter::new(BufWriter::new(File::create(file_path)?));
+            write_bam_header(&mut w);
+            Ok(Box::new(w))
+        } else {
+            let mut w = create_file(file_path)?;
+            write_sam_header(&mut *w);
+            Ok(w)
+        }
+    }
+}
+
+impl<R: Reads> CollectSamReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        file_expr: FormatExpr,
+        tags: Vec<SamTag>,
+        bam: bool,
+    ) -> Self {
+        CollectReads::with_writer(
+            reads,
+            selector_expr,
+            vec![file_expr],
+            SamWriter { tags, bam },
+        )
+    }
+}
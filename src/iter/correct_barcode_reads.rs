@@ -0,0 +1,664 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use std::io::{BufRead, BufReader};
+
+use crate::iter::*;
+use crate::parse_utils::*;
+
+pub struct CorrectBarcodeReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    label: Label,
+    // corrected barcode, distance, and ambiguous attributes
+    attrs: [Option<Attr>; 3],
+    whitelist: Vec<Vec<u8>>,
+    index: FxHashMap<u128, Neighbor>,
+    max_dist: u8,
+    dist_type: DistType,
+    posterior: Option<Posterior>,
+    // prior count for each whitelist barcode, including a pseudocount
+    priors: Vec<f64>,
+}
+
+struct Posterior {
+    min_posterior: f64,
+    phred_offset: u8,
+    attr: Option<Attr>,
+}
+
+/// The closest whitelist barcode to a sequence in the neighbor index.
+#[derive(Copy, Clone)]
+struct Neighbor {
+    idx: u32,
+    dist: u8,
+    // whether multiple whitelist barcodes are at the same distance
+    ambiguous: bool,
+}
+
+/// Bases used for substitutions and insertions when generating neighbors.
+const BASES: [u8; 5] = *b"ACGTN";
+/// Max length of sequences in the index, since each base is packed into 3 bits.
+const MAX_LEN: usize = 42;
+
+impl<R: Reads> CorrectBarcodeReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        whitelist_file: &str,
+        max_dist: usize,
+        dist_type: DistType,
+    ) -> Result<Self> {
+        transform_expr.check_size(1, 3, "correcting barcodes");
+        transform_expr.check_same_str_type("correcting barcodes");
+
+        let mut attrs = [None, None, None];
+        for (attr, after) in attrs.iter_mut().zip(transform_expr.after()) {
+            *attr = after.clone().map(|a| match a {
+                LabelOrAttr::Attr(a) => a,
+                _ => panic!("Expected type.label.attr after the \"->\" in the transform expression when correcting barcodes"),
+            });
+        }
+
+        let whitelist = load_whitelist(whitelist_file)?;
+        let max_dist = max_dist.min(u8::MAX as usize) as u8;
+        let index = build_index(&whitelist, max_dist, dist_type);
+        let priors = vec![1.0; whitelist.len()];
+
+        Ok(Self {
+            reads,
+            selector_expr,
+            label: transform_expr.before()[0].clone(),
+            attrs,
+            whitelist,
+            index,
+            max_dist,
+            dist_type,
+            posterior: None,
+            priors,
+        })
+    }
+
+    /// Choose corrections based on the posterior probability of each whitelist barcode, like
+    /// Cell Ranger.
+    ///
+    /// The likelihood of a whitelist barcode is computed with Hamming distance using the quality
+    /// scores of the barcode, and it is weighted by the prior count of the whitelist barcode
+    /// (see [`CorrectBarcodeReads::priors`]). A barcode is only corrected if the posterior
+    /// probability of the most likely whitelist barcode is at least `min_posterior` (e.g., 0.975).
+    /// Otherwise, the barcode is considered ambiguous. Barcodes that are in the whitelist are
+    /// never corrected and have a posterior probability of 1.
+    ///
+    /// If `posterior_attr` is specified, then it is set to the posterior probability as a float,
+    /// or false if there are no whitelist barcodes within the max distance.
+    /// Reads without quality scores are corrected without using posterior probabilities.
+    ///
+    /// This can only be used with [`DistType::Hamming`].
+    #[must_use]
+    pub fn posterior(
+        mut self,
+        min_posterior: f64,
+        phred_offset: u8,
+        posterior_attr: Option<Attr>,
+    ) -> Self {
+        assert!(
+            self.dist_type == DistType::Hamming,
+            "Posterior probabilities can only be computed with Hamming distance when correcting barcodes"
+        );
+        self.posterior = Some(Posterior {
+            min_posterior,
+            phred_offset,
+            attr: posterior_attr,
+        });
+        self
+    }
+
+    /// Set the prior counts of whitelist barcodes for computing posterior probabilities.
+    ///
+    /// The counts are typically the number of exact whitelist matches from a first pass over the
+    /// data (e.g., by counting barcodes with a distance of 0 in `for_each`). A pseudocount of 1 is
+    /// added to each count. Barcodes that are not in the whitelist are ignored.
+    #[must_use]
+    pub fn priors<B: AsRef<[u8]>>(mut self, counts: impl IntoIterator<Item = (B, usize)>) -> Self {
+        for (barcode, count) in counts {
+            let neighbor = encode(barcode.as_ref()).and_then(|key| self.index.get(&key));
+            if let Some(&Neighbor { idx, dist: 0, .. }) = neighbor {
+                self.priors[idx as usize] = (count as f64) + 1.0;
+            }
+        }
+        self
+    }
+
+    /// Find the most likely whitelist barcode and its posterior probability.
+    fn most_likely(&self, barcode: &[u8], qual: &[u8], phred_offset: u8) -> Option<(u32, u8, f64)> {
+        let mut best: Option<(u32, u8, f64)> = None;
+        let mut total = 0.0;
+
+        let mut visit = |seq: &[u8], dist: u8| {
+            let Some(&Neighbor { idx, dist: 0, .. }) =
+                encode(seq).and_then(|key| self.index.get(&key))
+            else {
+                return;
+            };
+
+            let likelihood = self.priors[idx as usize]
+                * barcode
+                    .iter()
+                    .zip(seq)
+                    .zip(qual)
+                    .map(|((&a, &b), &q)| {
+                        // N has an equal chance of being any base
+                        let p = if a == b'N' || a == b'n' {
+                            0.75
+                        } else {
+                            10.0f64.powf(-(q.saturating_sub(phred_offset) as f64) / 10.0)
+                        };
+                        if a.eq_ignore_ascii_case(&b) {
+                            1.0 - p
+                        } else {
+                            p / 3.0
+                        }
+                    })
+                    .product::<f64>();
+
+            total += likelihood;
+            if best.map(|(_, _, l)| likelihood > l).unwrap_or(true) {
+                best = Some((idx, dist, likelihood));
+            }
+        };
+
+        if self.max_dist > 0 {
+            hamming_neighbors(
+                &mut barcode.to_ascii_uppercase(),
+                0,
+                1,
+                self.max_dist,
+                &mut visit,
+            );
+        }
+
+        best.map(|(idx, dist, likelihood)| (idx, dist, likelihood / total))
+    }
+}
+
+impl<R: Reads> Reads for CorrectBarcodeReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "correcting barcodes",
+                })?)
+            {
+                continue;
+            }
+
+            let barcode = read
+                .substring(self.label.str_type, self.label.label)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "correcting barcodes",
+                })?;
+
+            let mut neighbor = encode(barcode).and_then(|key| self.index.get(&key).copied());
+            let mut posterior = None;
+
+            if let Some(Posterior {
+                min_posterior,
+                phred_offset,
+                ..
+            }) = self.posterior
+            {
+                let qual = read
+                    .substring_qual(self.label.str_type, self.label.label)
+                    .map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context: "correcting barcodes",
+                    })?;
+
+                // barcodes in the whitelist are not corrected
+                let in_whitelist = matches!(neighbor, Some(Neighbor { dist: 0, .. }));
+
+                if let (Some(qual), false) = (qual, in_whitelist) {
+                    (neighbor, posterior) = match self.most_likely(barcode, qual, phred_offset) {
+                        Some((idx, dist, p)) => (
+                            Some(Neighbor {
+                                idx,
+                                dist,
+                                ambiguous: p < min_posterior,
+                            }),
+                            Some(p),
+                        ),
+                        None => (None, None),
+                    };
+                } else if in_whitelist {
+                    posterior = Some(1.0);
+                }
+            }
+
+            let data = match neighbor {
+                Some(Neighbor {
+                    idx,
+                    dist,
+                    ambiguous: false,
+                }) => [
+                    Data::Bytes(self.whitelist[idx as usize].clone()),
+                    Data::UInt(dist as usize),
+                    Data::Bool(false),
+                ],
+                Some(Neighbor {
+                    dist,
+                    ambiguous: true,
+                    ..
+                }) => [
+                    Data::Bool(false),
+                    Data::UInt(dist as usize),
+                    Data::Bool(true),
+                ],
+                None => [Data::Bool(false), Data::Bool(false), Data::Bool(false)],
+            };
+
+            if let Some(Posterior {
+                attr: Some(attr), ..
+            }) = &self.posterior
+            {
+                // panic to make borrow checker happy
+                *read
+                    .data_mut(attr.str_type, attr.label, attr.attr)
+                    .unwrap_or_else(|e| panic!("Error correcting barcodes: {e}")) =
+                    posterior.map(Data::Float).unwrap_or(Data::Bool(false));
+            }
+
+            for (attr, data) in self.attrs.iter().zip(data) {
+                if let Some(attr) = attr {
+                    // panic to make borrow checker happy
+                    *read
+                        .data_mut(attr.str_type, attr.label, attr.attr)
+                        .unwrap_or_else(|e| panic!("Error correcting barcodes: {e}")) = data;
+                }
+            }
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
+
+/// Load unique barcodes from the first column of a (optionally gzipped) text file.
+fn load_whitelist(file: &str) -> Result<Vec<Vec<u8>>> {
+    let file_err = |e: std::io::Error| Error::FileIo {
+        file: file.to_owned(),
+        source: Box::new(e),
+    };
+
+    let f = std::fs::File::open(file).map_err(file_err)?;
+    let reader: Box<dyn BufRead> = if file.ends_with(".gz") {
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(f)))
+    } else {
+        Box::new(BufReader::new(f))
+    };
+
+    let mut seen = FxHashSet::default();
+    let mut res = Vec::new();
+
+    for line in reader.split(b'\n') {
+        let line = line.map_err(file_err)?;
+        let Some(line) = trim_ascii_whitespace(&line) else {
+            continue;
+        };
+        let barcode = line
+            .split(|c| c.is_ascii_whitespace())
+            .next()
+            .unwrap()
+            .to_ascii_uppercase();
+
+        if encode(&barcode).is_none() {
+            return Err(Error::Parse {
+                string: utf8(&barcode),
+                context: file.to_owned(),
+                reason: "barcodes must only contain A, C, G, T, or N and be at most 42 bases long",
+            });
+        }
+
+        if seen.insert(barcode.clone()) {
+            res.push(barcode);
+        }
+    }
+
+    Ok(res)
+}
+
+/// Map every sequence within `max_dist` of a whitelist barcode to its closest whitelist barcode.
+fn build_index(
+    whitelist: &[Vec<u8>],
+    max_dist: u8,
+    dist_type: DistType,
+) -> FxHashMap<u128, Neighbor> {
+    let mut index = FxHashMap::default();
+
+    for (idx, barcode) in whitelist.iter().enumerate() {
+        let idx = idx as u32;
+        let mut insert = |seq: &[u8], dist: u8| {
+            use std::collections::hash_map::Entry::*;
+            let Some(key) = encode(seq) else {
+                return;
+            };
+            let neighbor = Neighbor {
+                idx,
+                dist,
+                ambiguous: false,
+            };
+
+            match index.entry(key) {
+                Vacant(e) => {
+                    e.insert(neighbor);
+                }
+                Occupied(mut e) => {
+                    let curr = e.get_mut();
+                    if dist < curr.dist {
+                        *curr = neighbor;
+                    } else if dist == curr.dist && idx != curr.idx {
+                        curr.ambiguous = true;
+                    }
+                }
+            }
+        };
+
+        insert(barcode, 0);
+
+        if max_dist > 0 {
+            match dist_type {
+                DistType::Hamming => {
+                    hamming_neighbors(&mut barcode.clone(), 0, 1, max_dist, &mut insert)
+                }
+                DistType::Edit => edit_neighbors(barcode, 1, max_dist, &mut insert),
+            }
+        }
+    }
+
+    index
+}
+
+/// Generate sequences with substitutions at or after `start`.
+fn hamming_neighbors(
+    seq: &mut Vec<u8>,
+    start: usize,
+    dist: u8,
+    max_dist: u8,
+    f: &mut impl FnMut(&[u8], u8),
+) {
+    for i in start..seq.len() {
+        let c = seq[i];
+
+        for &b in BASES.iter().filter(|&&b| b != c) {
+            seq[i] = b;
+            f(seq, dist);
+
+            if dist < max_dist {
+                hamming_neighbors(seq, i + 1, dist + 1, max_dist, f);
+            }
+        }
+
+        seq[i] = c;
+    }
+}
+
+/// Generate sequences with substitutions, insertions, and deletions.
+///
+/// The same sequence may be generated multiple times with different distances.
+fn edit_neighbors(seq: &[u8], dist: u8, max_dist: u8, f: &mut impl FnMut(&[u8], u8)) {
+    let mut visit = |s: Vec<u8>| {
+        f(&s, dist);
+
+        if dist < max_dist {
+            edit_neighbors(&s, dist + 1, max_dist, f);
+        }
+    };
+
+    for i in 0..=seq.len() {
+        for &b in BASES.iter() {
+            let mut s = Vec::with_capacity(seq.len() + 1);
+            s.extend_from_slice(&seq[..i]);
+            s.push(b);
+            s.extend_from_slice(&seq[i..]);
+            visit(s);
+        }
+
+        if i < seq.len() {
+            let mut s = Vec::with_capacity(seq.len());
+            s.extend_from_slice(&seq[..i]);
+            s.extend_from_slice(&seq[i + 1..]);
+            visit(s);
+
+            for &b in BASES.iter().filter(|&&b| b != seq[i]) {
+                let mut s = seq.to_owned();
+                s[i] = b;
+                visit(s);
+            }
+        }
+    }
+}
+
+/// Pack a sequence into an integer, with a leading one bit to encode the length.
+fn encode(seq: &[u8]) -> Option<u128> {
+    if seq.len() > MAX_LEN {
+        return None;
+    }
+
+    let mut res = 1u128;
+
+    for &c in seq {
+        let b = match c.to_ascii_uppercase() {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            b'N' => 4,
+            _ => return None,
+        };
+        res = (res << 3) | b;
+    }
+
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fastq::*;
+
+    fn naive_hamming(a: &[u8], b: &[u8]) -> Option<usize> {
+        (a.len() == b.len()).then(|| a.iter().zip(b).filter(|(x, y)| x != y).count())
+    }
+
+    fn naive_edit(a: &[u8], b: &[u8]) -> Option<usize> {
+        let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+        for (i, &x) in a.iter().enumerate() {
+            let mut curr = vec![i + 1; b.len() + 1];
+
+            for (j, &y) in b.iter().enumerate() {
+                curr[j + 1] = (prev[j] + (x != y) as usize)
+                    .min(prev[j + 1] + 1)
+                    .min(curr[j] + 1);
+            }
+
+            prev = curr;
+        }
+
+        Some(prev[b.len()])
+    }
+
+    fn all_seqs(len: usize) -> Vec<Vec<u8>> {
+        let mut res = vec![Vec::new()];
+
+        for _ in 0..len {
+            res = res
+                .into_iter()
+                .flat_map(|s| {
+                    BASES.iter().map(move |&b| {
+                        let mut s = s.clone();
+                        s.push(b);
+                        s
+                    })
+                })
+                .collect();
+        }
+
+        res
+    }
+
+    fn get(index: &FxHashMap<u128, Neighbor>, seq: &[u8]) -> Option<(u32, u8, bool)> {
+        index
+            .get(&encode(seq).unwrap())
+            .map(|n| (n.idx, n.dist, n.ambiguous))
+    }
+
+    // compare every sequence that could be within max_dist against a brute force search
+    fn check_index(whitelist: &[&[u8]], max_dist: u8, dist_type: DistType) {
+        let whitelist = whitelist.iter().map(|b| b.to_vec()).collect::<Vec<_>>();
+        let index = build_index(&whitelist, max_dist, dist_type);
+        let dist_fn = match dist_type {
+            DistType::Hamming => naive_hamming,
+            DistType::Edit => naive_edit,
+        };
+        let len = whitelist[0].len();
+        let mut count = 0;
+
+        for seq in (len - max_dist as usize..=len + max_dist as usize).flat_map(all_seqs) {
+            let dists = whitelist
+                .iter()
+                .map(|b| dist_fn(&seq, b).unwrap_or(usize::MAX))
+                .collect::<Vec<_>>();
+            let min = *dists.iter().min().unwrap();
+            let neighbor = index.get(&encode(&seq).unwrap());
+
+            if min > max_dist as usize {
+                assert!(neighbor.is_none(), "{}", utf8(&seq));
+                continue;
+            }
+
+            let neighbor = neighbor.unwrap_or_else(|| panic!("missing {}", utf8(&seq)));
+            assert_eq!(neighbor.dist as usize, min, "{}", utf8(&seq));
+            assert_eq!(dists[neighbor.idx as usize], min, "{}", utf8(&seq));
+            assert_eq!(
+                neighbor.ambiguous,
+                dists.iter().filter(|&&d| d == min).count() > 1,
+                "{}",
+                utf8(&seq)
+            );
+            count += 1;
+        }
+
+        assert_eq!(index.len(), count);
+    }
+
+    #[test]
+    fn hamming_index() {
+        check_index(&[b"AAAA", b"AACC", b"TTTT", b"GNGN"], 1, DistType::Hamming);
+        check_index(&[b"AAAA", b"AACC", b"TTTT", b"GNGN"], 2, DistType::Hamming);
+
+        let whitelist = [b"ACGT".to_vec(), b"ACGG".to_vec()];
+        let index = build_index(&whitelist, 1, DistType::Hamming);
+        assert_eq!(get(&index, b"ACGT"), Some((0, 0, false)));
+        assert_eq!(get(&index, b"ACGG"), Some((1, 0, false)));
+        assert_eq!(get(&index, b"ACNT"), Some((0, 1, false)));
+        assert_eq!(get(&index, b"ACGA"), Some((0, 1, true)));
+        assert_eq!(get(&index, b"AGGA"), None);
+    }
+
+    #[test]
+    fn edit_index() {
+        check_index(&[b"ACGT", b"AGTT", b"CCCC"], 1, DistType::Edit);
+        check_index(&[b"ACGT", b"AGTT", b"CCCC"], 2, DistType::Edit);
+
+        // neighbors that are generated by longer paths of edits keep the true distance
+        let whitelist = [b"ACGT".to_vec()];
+        let index = build_index(&whitelist, 2, DistType::Edit);
+        assert_eq!(get(&index, b"ACGT"), Some((0, 0, false)));
+        assert_eq!(get(&index, b"ACGG"), Some((0, 1, false)));
+        assert_eq!(get(&index, b"CGTA"), Some((0, 2, false)));
+        assert_eq!(get(&index, b"ACGTAC"), Some((0, 2, false)));
+        assert_eq!(get(&index, b"ACGTACG"), None);
+    }
+
+    #[test]
+    fn encode_limit() {
+        assert_ne!(encode(b""), encode(b"A"));
+        assert_ne!(encode(b"A"), encode(b"AA"));
+        assert_eq!(encode(b"acgtn"), encode(b"ACGTN"));
+        assert!(encode(b"ACGX").is_none());
+        assert!(encode(&[b'N'; MAX_LEN]).is_some());
+        assert!(encode(&[b'A'; MAX_LEN + 1]).is_none());
+
+        // neighbors that are too long are skipped
+        let barcode = [b'T'; MAX_LEN].to_vec();
+        let index = build_index(std::slice::from_ref(&barcode), 1, DistType::Edit);
+        assert_eq!(get(&index, &barcode), Some((0, 0, false)));
+        assert_eq!(get(&index, &barcode[1..]), Some((0, 1, false)));
+        let mut sub = barcode.clone();
+        sub[MAX_LEN - 1] = b'N';
+        assert_eq!(get(&index, &sub), Some((0, 1, false)));
+        // the barcode, one deletion (the same at every position), and 4 substitutions per position,
+        // but no insertions
+        assert_eq!(index.len(), 1 + 1 + MAX_LEN * 4);
+    }
+
+    fn corrector(
+        whitelist: &[&[u8]],
+        dist_type: DistType,
+    ) -> CorrectBarcodeReads<Fastq1Reads<'static>> {
+        let whitelist = whitelist.iter().map(|b| b.to_vec()).collect::<Vec<_>>();
+
+        CorrectBarcodeReads {
+            reads: iter_fastq1_bytes(b"@r\nA\n+\nI\n").unwrap(),
+            selector_expr: crate::sel!(),
+            label: crate::label!(seq1.bc),
+            attrs: [None, None, None],
+            index: build_index(&whitelist, 1, dist_type),
+            priors: vec![1.0; whitelist.len()],
+            whitelist,
+            max_dist: 1,
+            dist_type,
+            posterior: None,
+        }
+    }
+
+    #[test]
+    fn posterior() {
+        let c = corrector(&[b"AAAA", b"CAAT", b"TTTT"], DistType::Hamming).posterior(0.9, 33, None);
+
+        // error probabilities of 0.1, 0.0001, 0.0001, and 0.01
+        // AAAA: 0.1 / 3 * 0.9999 * 0.9999 * 0.99
+        // CAAT: 0.9 * 0.9999 * 0.9999 * 0.01 / 3
+        let (idx, dist, p) = c.most_likely(b"CAAA", b"+II5", 33).unwrap();
+        assert_eq!((idx, dist), (0, 1));
+        assert!((p - 0.099 / (0.099 + 0.009)).abs() < 1e-9);
+
+        // both candidates mismatch the N, so only the priors of 3 and 1 matter
+        let c = corrector(&[b"AAAA", b"CAAA", b"TTTT"], DistType::Hamming)
+            .posterior(0.9, 33, None)
+            .priors([(b"AAAA", 2)]);
+        let (idx, dist, p) = c.most_likely(b"NAAA", b"IIII", 33).unwrap();
+        assert_eq!((idx, dist), (0, 1));
+        assert!((p - 0.75).abs() < 1e-9);
+
+        assert!(c.most_likely(b"GGGG", b"IIII", 33).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn posterior_edit() {
+        let _ = corrector(&[b"AAAA"], DistType::Edit).posterior(0.9, 33, None);
+    }
+}
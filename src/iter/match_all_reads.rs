@@ -0,0 +1,379 @@
+use memchr::memmem;
+
+use regex::bytes::Regex;
+
+use thread_local::*;
+
+use crate::inline_string::*;
+use crate::iter::*;
+use crate::seq_utils::*;
+
+pub struct MatchAllReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    label: Label,
+    hit_labels: Option<IndexedLabels>,
+    segment_labels: Option<IndexedLabels>,
+    count_attr: Option<Attr>,
+    matcher: Matcher,
+}
+
+enum Matcher {
+    Pattern(FormatExpr, MatchType),
+    // boxed because the thread local cache is large
+    Regex(Regex, Box<ThreadLocal<Regex>>),
+}
+
+impl<R: Reads> MatchAllReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        pattern: FormatExpr,
+        match_type: MatchType,
+    ) -> Self {
+        assert!(
+            matches!(
+                match_type,
+                MatchType::ExactSearch | MatchType::HammingSearch(_) | MatchType::EditSearch(_)
+            ),
+            "Only ExactSearch, HammingSearch, or EditSearch can be used when matching all occurrences of a pattern"
+        );
+
+        Self::new_matcher(
+            reads,
+            selector_expr,
+            transform_expr,
+            Matcher::Pattern(pattern, match_type),
+        )
+    }
+
+    pub fn new_regex(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        regex: &str,
+    ) -> Self {
+        Self::new_matcher(
+            reads,
+            selector_expr,
+            transform_expr,
+            Matcher::Regex(
+                Regex::new(regex).unwrap_or_else(|e| panic!("Error compiling regex: {e}")),
+                Box::new(ThreadLocal::new()),
+            ),
+        )
+    }
+
+    fn new_matcher(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        matcher: Matcher,
+    ) -> Self {
+        transform_expr.check_size(1, 3, "matching all occurrences");
+        transform_expr.check_same_str_type("matching all occurrences");
+
+        let label = |i: usize| {
+            transform_expr.after()[i].clone().map(|l| match l {
+                LabelOrAttr::Label(l) => l,
+                _ => panic!("Expected type.label after the \"->\" in the transform expression when matching all occurrences"),
+            })
+        };
+
+        Self {
+            reads,
+            selector_expr,
+            label: transform_expr.before()[0].clone(),
+            hit_labels: label(0).map(|l| IndexedLabels::new(l.label)),
+            segment_labels: label(1).map(|l| IndexedLabels::new(l.label)),
+            count_attr: transform_expr.after()[2].clone().map(|a| match a {
+                LabelOrAttr::Attr(a) => a,
+                _ => panic!("Expected type.label.attr after the \"->\" in the transform expression when matching all occurrences"),
+            }),
+            matcher,
+        }
+    }
+}
+
+impl<R: Reads> Reads for MatchAllReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching all occurrences",
+                })?)
+            {
+                continue;
+            }
+
+            let string = read
+                .substring(self.label.str_type, self.label.label)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching all occurrences",
+                })?;
+            let string_len = string.len();
+
+            let hits = match &self.matcher {
+                Matcher::Pattern(pattern, match_type) => {
+                    let pattern = pattern.format(read, false).map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context: "matching all occurrences",
+                    })?;
+                    find_all(string, &pattern, *match_type)
+                }
+                Matcher::Regex(regex, regex_local) => {
+                    let regex = regex_local.get_or(|| regex.clone());
+                    regex
+                        .find_iter(string)
+                        .filter(|m| !m.is_empty())
+                        .map(|m| (m.start(), m.end()))
+                        .collect()
+                }
+            };
+
+            let str_mappings = read.str_mappings_mut(self.label.str_type).unwrap();
+            let offset = str_mappings.mapping(self.label.label).unwrap().start;
+            let indexed =
+                |labels: &Option<IndexedLabels>, i: usize| labels.as_ref().map(|l| l.get(i));
+
+            let mut prev_end = 0;
+
+            for (i, &(start, end)) in hits.iter().enumerate() {
+                // panic to make borrow checker happy
+                str_mappings
+                    .add_mapping(indexed(&self.hit_labels, i), offset + start, end - start)
+                    .unwrap_or_else(|e| panic!("Error matching all occurrences: {e}"));
+                str_mappings
+                    .add_mapping(
+                        indexed(&self.segment_labels, i),
+                        offset + prev_end,
+                        start - prev_end,
+                    )
+                    .unwrap_or_else(|e| panic!("Error matching all occurrences: {e}"));
+                prev_end = end;
+            }
+
+            str_mappings
+                .add_mapping(
+                    indexed(&self.segment_labels, hits.len()),
+                    offset + prev_end,
+                    string_len - prev_end,
+                )
+                .unwrap_or_else(|e| panic!("Error matching all occurrences: {e}"));
+
+            if let Some(attr) = &self.count_attr {
+                // panic to make borrow checker happy
+                *read
+                    .data_mut(attr.str_type, attr.label, attr.attr)
+                    .unwrap_or_else(|e| panic!("Error matching all occurrences: {e}")) =
+                    Data::UInt(hits.len());
+            }
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
+
+/// Find the start and end indexes of all non-overlapping occurrences of a pattern, from left to
+/// right.
+fn find_all(string: &[u8], pattern: &[u8], match_type: MatchType) -> Vec<(usize, usize)> {
+    let pattern_len = pattern.len();
+    let mut res = Vec::new();
+
+    if pattern_len == 0 {
+        return res;
+    }
+
+    use MatchType::*;
+    match match_type {
+        ExactSearch => {
+            res.extend(memmem::find_iter(string, pattern).map(|i| (i, i + pattern_len)));
+        }
+        HammingSearch(t) => {
+            let t = t.get(pattern_len);
+            let mut i = 0;
+
+            while i + pattern_len <= string.len() {
+                let Some(matches) = hamming(&string[i..i + pattern_len], pattern, t, false) else {
+                    i += 1;
+                    continue;
+                };
+
+                // use the best window that overlaps the first window that passes the threshold
+                let mut best = (matches, i);
+                for j in i + 1..(i + pattern_len).min(string.len() - pattern_len + 1) {
+                    if let Some(m) = hamming(&string[j..j + pattern_len], pattern, t, false) {
+                        if m > best.0 {
+                            best = (m, j);
+                        }
+                    }
+                }
+
+                res.push((best.1, best.1 + pattern_len));
+                i = best.1 + pattern_len;
+            }
+        }
+        EditSearch(t) => {
+            // empty matches are not allowed
+            let k = t.get(pattern_len).min(pattern_len - 1);
+            let mut pos = 0;
+
+            loop {
+                let scores = myers_scores(&string[pos..], pattern, true, false);
+                let Some(first) = scores.iter().position(|&s| s <= k) else {
+                    break;
+                };
+
+                // use the end with the fewest edits in the run of ends that pass the threshold
+                let mut best = first;
+                for (j, &s) in scores.iter().enumerate().skip(first) {
+                    if s > k {
+                        break;
+                    }
+                    if s < scores[best] {
+                        best = j;
+                    }
+                }
+
+                let end = pos + best;
+                // align backwards from the end of the match to find where it starts
+                let string_rev = string[pos..end].iter().rev().copied().collect::<Vec<_>>();
+                let pattern_rev = pattern.iter().rev().copied().collect::<Vec<_>>();
+                let (_, len) = myers(&string_rev, &pattern_rev, false, false);
+
+                res.push((end - len, end));
+                pos = end;
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fastq::iter_fastq1_bytes;
+
+    use MatchType::*;
+    use Threshold::*;
+
+    fn segments(seq: &[u8], reads: impl Reads) -> Vec<String> {
+        let read = &reads.run_collect_reads().unwrap()[0];
+        assert_eq!(read.to_fastq1().1, seq);
+        (0..)
+            .map_while(|i| {
+                read.substring(
+                    StrType::Seq(1),
+                    InlineString::new(format!("seg_{i}").as_bytes()),
+                )
+                .ok()
+                .map(utf8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(find_all(b"ACGACGTT", b"ACG", ExactSearch), [(0, 3), (3, 6)]);
+        // occurrences cannot overlap
+        assert_eq!(find_all(b"AAAAA", b"AA", ExactSearch), [(0, 2), (2, 4)]);
+        assert_eq!(find_all(b"ACGT", b"", ExactSearch), []);
+        assert_eq!(find_all(b"AC", b"ACG", ExactSearch), []);
+    }
+
+    #[test]
+    fn hamming() {
+        assert_eq!(
+            find_all(b"ACGTACGT", b"ACGT", HammingSearch(Count(4))),
+            [(0, 4), (4, 8)]
+        );
+        assert_eq!(
+            find_all(b"ACCTACGA", b"ACGT", HammingSearch(Count(3))),
+            [(0, 4), (4, 8)]
+        );
+        // the best window that overlaps the first passing window is used
+        assert_eq!(
+            find_all(b"AAAAC", b"AAAC", HammingSearch(Count(3))),
+            [(1, 5)]
+        );
+        assert_eq!(
+            find_all(b"AAAAAAA", b"AAA", HammingSearch(Count(3))),
+            [(0, 3), (3, 6)]
+        );
+        assert_eq!(find_all(b"ACG", b"ACGT", HammingSearch(Count(0))), []);
+    }
+
+    #[test]
+    fn edit() {
+        assert_eq!(
+            find_all(b"ACGTACGT", b"ACGT", EditSearch(Count(0))),
+            [(0, 4), (4, 8)]
+        );
+        assert_eq!(
+            find_all(b"ACGTTACGT", b"ACGT", EditSearch(Count(0))),
+            [(0, 4), (5, 9)]
+        );
+        // the first end with the fewest edits is used, so `ACGG` and `ACGGT` are not used
+        assert_eq!(
+            find_all(b"TTACTTTACGGTTT", b"ACGT", EditSearch(Count(1))),
+            [(2, 5), (7, 10)]
+        );
+        assert_eq!(
+            find_all(b"AAAAAAA", b"AAA", EditSearch(Count(0))),
+            [(0, 3), (3, 6)]
+        );
+    }
+
+    #[test]
+    fn segment_boundaries() {
+        let reads = iter_fastq1_bytes(b"@r\nACGTTACGACG\n+\nIIIIIIIIIII\n")
+            .unwrap()
+            .match_all(
+                crate::sel!(),
+                crate::tr!(seq1.* -> seq1.hit, seq1.seg, seq1.*.count),
+                "ACG",
+                ExactSearch,
+            );
+        assert_eq!(segments(b"ACGTTACGACG", reads), ["", "TT", "", ""]);
+
+        let reads = iter_fastq1_bytes(b"@r\nTTACGT\n+\nIIIIII\n")
+            .unwrap()
+            .match_all(
+                crate::sel!(),
+                crate::tr!(seq1.* -> seq1.hit, seq1.seg, seq1.*.count),
+                "GGGG",
+                ExactSearch,
+            );
+        assert_eq!(segments(b"TTACGT", reads), ["TTACGT"]);
+    }
+
+    #[test]
+    fn regex() {
+        let reads = iter_fastq1_bytes(b"@r\nAAGGAACCGGTT\n+\nIIIIIIIIIIII\n")
+            .unwrap()
+            .match_regex_all(
+                crate::sel!(),
+                crate::tr!(seq1.* -> seq1.hit, seq1.seg, seq1.*.count),
+                "GG|C*",
+            );
+        // empty matches are ignored and adjacent matches produce empty segments
+        assert_eq!(segments(b"AAGGAACCGGTT", reads), ["AA", "AA", "", "TT"]);
+    }
+}
@@ -2,7 +2,12 @@ use block_aligner::{cigar::*, scan_block::*, scores::*};
 
 use memchr::memmem;
 
+use aho_corasick::AhoCorasick;
+
+use std::collections::BTreeMap;
+
 use crate::iter::*;
+use crate::seq_utils::*;
 
 pub struct MatchAnyReads<R: Reads> {
     reads: R,
@@ -11,6 +16,16 @@ pub struct MatchAnyReads<R: Reads> {
     new_labels: [Option<Label>; 3],
     patterns: Patterns,
     match_type: MatchType,
+    strand: Strand,
+    strand_attr: Option<Attr>,
+    // whether N in the read matches any base, if IUPAC codes are enabled
+    iupac: Option<bool>,
+    // attributes for the best number of matches, the second best pattern, and its number of matches
+    score_attrs: [Option<Attr>; 3],
+    min_margin: Option<(usize, Option<Attr>)>,
+    edits_attr: Option<Attr>,
+    // used instead of matching each pattern separately, if possible
+    literal_patterns: Option<LiteralPatterns>,
 }
 
 impl<R: Reads> MatchAnyReads<R> {
@@ -32,21 +47,138 @@ impl<R: Reads> MatchAnyReads<R> {
         }
         transform_expr.check_same_str_type("matching patterns");
 
-        Self {
+        for (i, pattern) in patterns.patterns().iter().enumerate() {
+            let pattern_match_type = pattern
+                .match_type
+                .get(match_type, 0)
+                .unwrap_or_else(|e| panic!("Error in the match type of pattern {i}: {e}"));
+            assert!(
+                pattern_match_type.fits(&match_type),
+                "The match type of pattern {i} ({pattern_match_type:?}) cannot be used with the mappings of {match_type:?}"
+            );
+        }
+
+        let mut res = Self {
             reads,
             selector_expr,
             label: transform_expr.before()[0].clone(),
             new_labels,
             patterns,
             match_type,
+            strand: Strand::Forward,
+            strand_attr: None,
+            iupac: None,
+            score_attrs: [None, None, None],
+            min_margin: None,
+            edits_attr: None,
+            literal_patterns: None,
+        };
+        res.build_literal_patterns();
+        res
+    }
+
+    /// Build an Aho-Corasick automaton to search for all patterns at once, if they are all
+    /// literal strings and exact matching is used.
+    fn build_literal_patterns(&mut self) {
+        self.literal_patterns = None;
+
+        if !matches!(
+            self.match_type,
+            MatchType::ExactSearch | MatchType::ExactPrefix
+        ) || self.iupac.is_some()
+        {
+            return;
         }
+
+        let Some(literals) = self
+            .patterns
+            .patterns()
+            .iter()
+            .map(|p| {
+                p.match_type
+                    .is_default()
+                    .then(|| p.expr.literal())
+                    .flatten()
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+
+        self.literal_patterns = Some(LiteralPatterns::new(literals, self.strand));
+    }
+
+    /// Set which orientations of the patterns to match.
+    ///
+    /// If both orientations are matched, then the best match is used, with ties going to the
+    /// forward orientation. If `strand_attr` is specified, then it is set to `"+"` or `"-"`
+    /// depending on the orientation of the matched pattern, or false if no pattern is matched.
+    #[must_use]
+    pub fn strand(mut self, strand: Strand, strand_attr: Option<Attr>) -> Self {
+        self.strand = strand;
+        self.strand_attr = strand_attr;
+        self.build_literal_patterns();
+        self
+    }
+
+    /// Treat IUPAC codes (e.g., `N`, `R`, or `W`) in the patterns and the read as degenerate bases.
+    ///
+    /// Two bases match if they have any nucleotide in common, so `N` in a pattern matches any base.
+    /// If `read_n_matches` is false, then `N` in the read is always counted as a mismatch.
+    /// Bases are compared case-insensitively.
+    #[must_use]
+    pub fn iupac(mut self, read_n_matches: bool) -> Self {
+        self.iupac = Some(read_n_matches);
+        self.build_literal_patterns();
+        self
+    }
+
+    /// Record the number of matching bases of the matched pattern, along with the second best
+    /// pattern and its number of matching bases.
+    ///
+    /// All patterns are checked when this is used, even if there is an exact match, but the matched
+    /// pattern is the same as without this. The second best pattern is the pattern with the most
+    /// matching bases out of the other patterns, so it can have as many or more matching bases than
+    /// the matched pattern. Each attribute is set to false if there is no such pattern.
+    #[must_use]
+    pub fn scores(
+        mut self,
+        matches_attr: Option<Attr>,
+        second_pattern_attr: Option<Attr>,
+        second_matches_attr: Option<Attr>,
+    ) -> Self {
+        self.score_attrs = [matches_attr, second_pattern_attr, second_matches_attr];
+        self
+    }
+
+    /// Reject matches where the second best pattern is close to the best pattern.
+    ///
+    /// If the matched pattern does not have at least `min_margin` more matching bases than the
+    /// second best pattern (see [`MatchAnyReads::scores`]), then the read is treated as if no
+    /// pattern matched. If `ambiguous_attr` is specified, then it is set to whether the match was
+    /// rejected. All patterns are checked when this is used, even if there is an exact match.
+    #[must_use]
+    pub fn min_margin(mut self, min_margin: usize, ambiguous_attr: Option<Attr>) -> Self {
+        self.min_margin = Some((min_margin, ambiguous_attr));
+        self
+    }
+
+    /// Record the pattern length minus the number of matching bases of the matched pattern.
+    ///
+    /// This is the number of edits for the edit-distance-based match types and the number of
+    /// mismatches for the Hamming-distance-based match types. It is set to false if no pattern is
+    /// matched.
+    #[must_use]
+    pub fn edits(mut self, edits_attr: Option<Attr>) -> Self {
+        self.edits_attr = edits_attr;
+        self
     }
 }
 
 impl<R: Reads> Reads for MatchAnyReads<R> {
     fn next_chunk(&self) -> Result<Vec<Read>> {
         let mut reads = self.reads.next_chunk()?;
-        let mut aligner: Option<Box<dyn Aligner>> = None;
+        let mut aligners = Aligners::default();
 
         for read in reads.iter_mut() {
             if !(self
@@ -69,151 +201,199 @@ impl<R: Reads> Reads for MatchAnyReads<R> {
                     context: "matching patterns",
                 })?;
 
-            if aligner.is_none() {
-                match self.match_type {
-                    MatchType::GlobalAln(_) => {
-                        aligner =
-                            Some(Box::new(GlobalLocalAligner::<false>::new(string.len() * 2)));
-                    }
-                    MatchType::LocalAln { .. } => {
-                        aligner = Some(Box::new(GlobalLocalAligner::<true>::new(string.len() * 2)));
-                    }
-                    MatchType::PrefixAln { .. } => {
-                        aligner =
-                            Some(Box::new(PrefixSuffixAligner::<true>::new(string.len() * 2)));
-                    }
-                    MatchType::SuffixAln { .. } => {
-                        aligner = Some(Box::new(PrefixSuffixAligner::<false>::new(
-                            string.len() * 2,
-                        )));
-                    }
-                    _ => (),
-                }
-            }
+            // IUPAC matching is symmetric, so Ns in the read are replaced if they should not match
+            let masked;
+            let string =
+                if self.iupac == Some(false) && string.iter().any(|&c| c == b'N' || c == b'n') {
+                    masked = mask_n(string);
+                    &masked
+                } else {
+                    string
+                };
 
-            let mut max_matches = 0;
-            let mut max_pattern = None;
-            let mut max_cut_pos1 = 0;
-            let mut max_cut_pos2 = 0;
-
-            for pattern in self.patterns.patterns() {
-                let pattern_str =
-                    pattern
-                        .expr
-                        .format(read, false)
-                        .map_err(|e| Error::NameError {
-                            source: e,
-                            read: read.clone(),
-                            context: "matching patterns",
-                        })?;
-                let pattern_len = pattern_str.len();
-
-                if max_matches >= pattern_len {
-                    continue;
-                }
+            let mut best = BestMatches::default();
+            // all patterns must be checked to find the second best pattern
+            let exhaustive =
+                self.min_margin.is_some() || self.score_attrs.iter().any(|a| a.is_some());
+
+            if let Some(literal_patterns) = &self.literal_patterns {
+                let prefix = self.match_type == MatchType::ExactPrefix;
+                let found = literal_patterns.find(string, prefix);
+                // the first matching pattern is used, unless all patterns must be checked
+                let found = if exhaustive {
+                    &found[..]
+                } else {
+                    &found[..found.len().min(1)]
+                };
 
-                use MatchType::*;
-                let matches = match self.match_type {
-                    Exact => {
-                        if string == pattern_str {
-                            Some((pattern_len, pattern_len, 0))
-                        } else {
-                            None
-                        }
-                    }
-                    ExactPrefix => {
-                        if pattern_len <= string.len() && &string[..pattern_len] == &pattern_str {
-                            Some((pattern_len, pattern_len, 0))
-                        } else {
-                            None
-                        }
+                for &(i, pattern_max) in found {
+                    let pattern = &self.patterns.patterns()[i];
+                    best.update(
+                        literal_patterns.literals[i].clone(),
+                        &pattern.attrs,
+                        pattern_max,
+                    );
+                    best.max_final = true;
+                }
+            } else {
+                for pattern in self.patterns.patterns() {
+                    let pattern_str =
+                        pattern
+                            .expr
+                            .format(read, false)
+                            .map_err(|e| Error::NameError {
+                                source: e,
+                                read: read.clone(),
+                                context: "matching patterns",
+                            })?;
+                    let pattern_len = pattern_str.len();
+                    let match_type = pattern
+                        .match_type
+                        .get(self.match_type, pattern_len)
+                        .unwrap();
+
+                    // patterns that cannot beat the best match are only checked for the second best
+                    // pattern, which an empty pattern can never be
+                    let skipped = best.max_matches >= pattern_len;
+                    if skipped && (!exhaustive || pattern_len == 0) {
+                        continue;
                     }
-                    ExactSuffix => {
-                        if pattern_len <= string.len()
-                            && &string[string.len() - pattern_len..] == &pattern_str
-                        {
-                            Some((pattern_len, string.len() - pattern_len, 0))
-                        } else {
-                            None
+
+                    let revcomp_str = match self.strand {
+                        Strand::Forward => None,
+                        Strand::RevComp | Strand::Both => Some(revcomp(&pattern_str)),
+                    };
+                    let orientations = match self.strand {
+                        Strand::Forward => [Some((&pattern_str, false)), None],
+                        Strand::RevComp => [Some((revcomp_str.as_ref().unwrap(), true)), None],
+                        Strand::Both => [
+                            Some((&pattern_str, false)),
+                            Some((revcomp_str.as_ref().unwrap(), true)),
+                        ],
+                    };
+
+                    // best match across both orientations of the pattern
+                    let mut pattern_max: Option<(usize, usize, usize, bool)> = None;
+
+                    for (oriented_str, is_revcomp) in orientations.into_iter().flatten() {
+                        let matches = match_pattern(
+                            match_type,
+                            string,
+                            oriented_str,
+                            self.iupac.is_some(),
+                            aligners.get(match_type, string.len() * 2, self.iupac.is_some()),
+                        );
+
+                        if let Some((matches, mut cut_pos1, mut cut_pos2)) = matches {
+                            // convert to the mappings of the operation-level match type
+                            if std::mem::discriminant(&match_type)
+                                != std::mem::discriminant(&self.match_type)
+                            {
+                                let (start, end) =
+                                    match_type.region(string.len(), cut_pos1, cut_pos2);
+                                (cut_pos1, cut_pos2) =
+                                    self.match_type.cut_positions(string.len(), start, end);
+                            }
+
+                            if pattern_max.map(|(m, ..)| matches > m).unwrap_or(true) {
+                                pattern_max = Some((matches, cut_pos1, cut_pos2, is_revcomp));
+                            }
+
+                            if matches >= pattern_len {
+                                break;
+                            }
                         }
                     }
-                    ExactSearch => memmem::find(string, &pattern_str)
-                        .map(|i| (pattern_len, i, i + pattern_len)),
-                    Hamming(t) => {
-                        let t = t.get(pattern_len);
-                        hamming(string, &pattern_str, t).map(|m| (m, pattern_len, 0))
-                    }
-                    HammingPrefix(t) => {
-                        if pattern_len <= string.len() {
-                            let t = t.get(pattern_len);
-                            hamming(&string[..pattern_len], &pattern_str, t)
-                                .map(|m| (m, pattern_len, 0))
-                        } else {
-                            None
-                        }
+
+                    if let Some(pattern_max) = pattern_max {
+                        best.update(pattern_str, &pattern.attrs, pattern_max);
                     }
-                    HammingSuffix(t) => {
-                        if pattern_len <= string.len() {
-                            let t = t.get(pattern_len);
-                            hamming(&string[string.len() - pattern_len..], &pattern_str, t)
-                                .map(|m| (m, string.len() - pattern_len, 0))
-                        } else {
-                            None
+
+                    if !skipped && best.max_matches >= pattern_len {
+                        if !exhaustive {
+                            break;
                         }
+                        best.max_final = true;
                     }
-                    HammingSearch(t) => {
-                        let t = t.get(pattern_len);
-                        hamming_search(string, &pattern_str, t)
-                    }
-                    GlobalAln(identity) => aligner
-                        .as_mut()
-                        .unwrap()
-                        .align(string, &pattern_str, identity, identity)
-                        .map(|(m, _, end_idx)| (m, end_idx, 0)),
-                    LocalAln { identity, overlap } => {
-                        aligner
-                            .as_mut()
-                            .unwrap()
-                            .align(string, &pattern_str, identity, overlap)
-                    }
-                    PrefixAln { identity, overlap } => {
-                        let additional =
-                            ((1.0 - identity).max(0.0) * (pattern_len as f64)).ceil() as usize;
-                        let len = string.len().min(pattern_len + additional);
-                        aligner
-                            .as_mut()
-                            .unwrap()
-                            .align(&string[..len], &pattern_str, identity, overlap)
-                            .map(|(m, _, end_idx)| (m, end_idx, 0))
-                    }
-                    SuffixAln { identity, overlap } => {
-                        let additional =
-                            ((1.0 - identity).max(0.0) * (pattern_len as f64)).ceil() as usize;
-                        let len = string.len().min(pattern_len + additional);
-                        aligner
-                            .as_mut()
-                            .unwrap()
-                            .align(
-                                &string[string.len() - len..],
-                                &pattern_str,
-                                identity,
-                                overlap,
-                            )
-                            .map(|(m, start_idx, _)| (m, string.len() - len + start_idx, 0))
-                    }
-                };
+                }
+            }
 
-                if let Some((matches, cut_pos1, cut_pos2)) = matches {
-                    if matches > max_matches {
-                        max_matches = matches;
-                        max_pattern = Some((pattern_str, &pattern.attrs));
-                        max_cut_pos1 = cut_pos1;
-                        max_cut_pos2 = cut_pos2;
+            let BestMatches {
+                max_matches,
+                mut max_pattern,
+                max_cut_pos1,
+                max_cut_pos2,
+                max_is_revcomp,
+                second_matches,
+                second_pattern,
+                ..
+            } = best;
+
+            // treat near-ties as if no pattern matched
+            let ambiguous = match self.min_margin {
+                Some((min_margin, _)) => {
+                    max_pattern.is_some()
+                        && second_pattern.is_some()
+                        && max_matches.saturating_sub(second_matches) < min_margin
+                }
+                None => false,
+            };
+            if ambiguous {
+                max_pattern = None;
+            }
 
-                        if max_matches >= pattern_len {
-                            break;
+            let [matches_attr, second_pattern_attr, second_matches_attr] = &self.score_attrs;
+            let attrs = [
+                (
+                    self.strand_attr.as_ref(),
+                    match (&max_pattern, max_is_revcomp) {
+                        (Some(_), false) => Data::Bytes(b"+".to_vec()),
+                        (Some(_), true) => Data::Bytes(b"-".to_vec()),
+                        (None, _) => Data::Bool(false),
+                    },
+                ),
+                (
+                    matches_attr.as_ref(),
+                    match max_pattern {
+                        Some(_) => Data::UInt(max_matches),
+                        None => Data::Bool(false),
+                    },
+                ),
+                (
+                    second_pattern_attr.as_ref(),
+                    match &second_pattern {
+                        Some(p) => Data::Bytes(p.clone()),
+                        None => Data::Bool(false),
+                    },
+                ),
+                (
+                    second_matches_attr.as_ref(),
+                    match second_pattern {
+                        Some(_) => Data::UInt(second_matches),
+                        None => Data::Bool(false),
+                    },
+                ),
+                (
+                    self.min_margin.as_ref().and_then(|(_, a)| a.as_ref()),
+                    Data::Bool(ambiguous),
+                ),
+                (
+                    self.edits_attr.as_ref(),
+                    match &max_pattern {
+                        Some((pattern_str, _)) => {
+                            Data::UInt(pattern_str.len().saturating_sub(max_matches))
                         }
-                    }
+                        None => Data::Bool(false),
+                    },
+                ),
+            ];
+
+            for (attr, data) in attrs {
+                if let Some(attr) = attr {
+                    // panic to make borrow checker happy
+                    *read
+                        .data_mut(attr.str_type, attr.label, attr.attr)
+                        .unwrap_or_else(|e| panic!("Error matching patterns: {e}")) = data;
                 }
             }
 
@@ -298,52 +478,265 @@ impl<R: Reads> Reads for MatchAnyReads<R> {
     }
 }
 
-fn hamming(a: &[u8], b: &[u8], threshold: usize) -> Option<usize> {
-    if a.len() != b.len() {
-        return None;
+/// The best and second best matching patterns for a read.
+#[derive(Default)]
+struct BestMatches<'a> {
+    max_matches: usize,
+    max_pattern: Option<(Vec<u8>, &'a [Data])>,
+    max_cut_pos1: usize,
+    max_cut_pos2: usize,
+    max_is_revcomp: bool,
+    // whether the best match would stop the search if all patterns were not checked
+    max_final: bool,
+    second_matches: usize,
+    second_pattern: Option<Vec<u8>>,
+}
+
+impl<'a> BestMatches<'a> {
+    /// Update with the best match of a pattern, with ties going to the earlier pattern.
+    ///
+    /// Once the best match is final, later patterns can only become the second best pattern.
+    fn update(
+        &mut self,
+        pattern_str: Vec<u8>,
+        pattern_attrs: &'a [Data],
+        (matches, cut_pos1, cut_pos2, is_revcomp): (usize, usize, usize, bool),
+    ) {
+        if matches > self.max_matches && !self.max_final {
+            if let Some((prev_str, _)) = self.max_pattern.take() {
+                self.second_matches = self.max_matches;
+                self.second_pattern = Some(prev_str);
+            }
+
+            self.max_matches = matches;
+            self.max_pattern = Some((pattern_str, pattern_attrs));
+            self.max_is_revcomp = is_revcomp;
+            self.max_cut_pos1 = cut_pos1;
+            self.max_cut_pos2 = cut_pos2;
+        } else if matches > self.second_matches {
+            self.second_matches = matches;
+            self.second_pattern = Some(pattern_str);
+        }
     }
+}
+
+/// Literal patterns that are searched for at once with an Aho-Corasick automaton.
+struct LiteralPatterns {
+    automaton: AhoCorasick,
+    literals: Vec<Vec<u8>>,
+    max_len: usize,
+    strand: Strand,
+}
 
-    let a_ptr = a.as_ptr();
-    let b_ptr = b.as_ptr();
-    let n = a.len();
-    let mut res = 0;
-    let mut i = 0;
+impl LiteralPatterns {
+    fn new(literals: Vec<Vec<u8>>, strand: Strand) -> Self {
+        // reverse complements of the patterns come after the forward patterns
+        let oriented: Vec<Vec<u8>> = match strand {
+            Strand::Forward => literals.clone(),
+            Strand::RevComp => literals.iter().map(|l| revcomp(l)).collect(),
+            Strand::Both => literals
+                .iter()
+                .cloned()
+                .chain(literals.iter().map(|l| revcomp(l)))
+                .collect(),
+        };
+        let automaton = AhoCorasick::new(&oriented)
+            .unwrap_or_else(|e| panic!("Error building automaton for matching patterns: {e}"));
+        let max_len = literals.iter().map(|l| l.len()).max().unwrap_or(0);
 
-    unsafe {
-        while i < (n / 8) * 8 {
-            let a_word = std::ptr::read_unaligned(a_ptr.add(i) as *const u64);
-            let b_word = std::ptr::read_unaligned(b_ptr.add(i) as *const u64);
+        Self {
+            automaton,
+            literals,
+            max_len,
+            strand,
+        }
+    }
 
-            let xor = a_word ^ b_word;
-            let or1 = xor | (xor >> 1);
-            let or2 = or1 | (or1 >> 2);
-            let or3 = or2 | (or2 >> 4);
-            let mask = or3 & 0x0101010101010101u64;
-            res += mask.count_ones() as usize;
+    /// Find the leftmost match of each pattern, in the same order as the patterns.
+    ///
+    /// Returns the index of each matched pattern, along with the number of matching bases, the cut
+    /// positions of the match, and whether the reverse complement of the pattern was matched.
+    fn find(&self, string: &[u8], prefix: bool) -> Vec<(usize, (usize, usize, usize, bool))> {
+        let n = self.literals.len();
+        // start of the leftmost match for each pattern and orientation
+        let mut starts = BTreeMap::new();
+
+        for m in self.automaton.find_overlapping_iter(string) {
+            if prefix && m.end() > self.max_len {
+                break;
+            }
+            if prefix && m.start() != 0 {
+                continue;
+            }
 
-            i += 8;
+            let (i, is_revcomp) = match self.strand {
+                Strand::Forward => (m.pattern().as_usize(), false),
+                Strand::RevComp => (m.pattern().as_usize(), true),
+                Strand::Both => (m.pattern().as_usize() % n, m.pattern().as_usize() >= n),
+            };
+            // matches are found in order of their end positions, so the first match of each
+            // pattern is the leftmost one
+            starts.entry((i, is_revcomp)).or_insert(m.start());
         }
 
-        while i < n {
-            res += (*a_ptr.add(i) != *b_ptr.add(i)) as usize;
-            i += 1;
+        let mut res: Vec<(usize, (usize, usize, usize, bool))> = Vec::new();
+
+        // forward matches come before reverse complement matches, so they are preferred for ties
+        for ((i, is_revcomp), start) in starts {
+            if res.last().map(|&(j, _)| j == i).unwrap_or(false) {
+                continue;
+            }
+
+            let len = self.literals[i].len();
+            let cut_pos = if prefix {
+                (len, 0)
+            } else {
+                (start, start + len)
+            };
+            res.push((i, (len, cut_pos.0, cut_pos.1, is_revcomp)));
         }
+
+        res
     }
+}
+
+/// Match a pattern in a string.
+///
+/// Returns the number of matching bases and the cut positions of the match.
+pub(crate) fn match_pattern(
+    match_type: MatchType,
+    string: &[u8],
+    pattern: &[u8],
+    iupac: bool,
+    aligner: &mut Option<Box<dyn Aligner>>,
+) -> Option<(usize, usize, usize)> {
+    let pattern_len = pattern.len();
+    let eq = |a: &[u8], b: &[u8]| {
+        if iupac {
+            a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| iupac_eq(a, b))
+        } else {
+            a == b
+        }
+    };
 
-    let matches = n - res;
+    use MatchType::*;
+    match match_type {
+        Exact => {
+            if eq(string, pattern) {
+                Some((pattern_len, pattern_len, 0))
+            } else {
+                None
+            }
+        }
+        ExactPrefix => {
+            if pattern_len <= string.len() && eq(&string[..pattern_len], pattern) {
+                Some((pattern_len, pattern_len, 0))
+            } else {
+                None
+            }
+        }
+        ExactSuffix => {
+            if pattern_len <= string.len() && eq(&string[string.len() - pattern_len..], pattern) {
+                Some((pattern_len, string.len() - pattern_len, 0))
+            } else {
+                None
+            }
+        }
+        ExactSearch => {
+            let i = if iupac {
+                string.windows(pattern_len).position(|w| eq(w, pattern))
+            } else {
+                memmem::find(string, pattern)
+            };
+            i.map(|i| (pattern_len, i, i + pattern_len))
+        }
+        Hamming(t) => {
+            let t = t.get(pattern_len);
+            hamming(string, pattern, t, iupac).map(|m| (m, pattern_len, 0))
+        }
+        HammingPrefix(t) => {
+            if pattern_len <= string.len() {
+                let t = t.get(pattern_len);
+                hamming(&string[..pattern_len], pattern, t, iupac).map(|m| (m, pattern_len, 0))
+            } else {
+                None
+            }
+        }
+        HammingSuffix(t) => {
+            if pattern_len <= string.len() {
+                let t = t.get(pattern_len);
+                hamming(&string[string.len() - pattern_len..], pattern, t, iupac)
+                    .map(|m| (m, string.len() - pattern_len, 0))
+            } else {
+                None
+            }
+        }
+        HammingSearch(t) => {
+            let t = t.get(pattern_len);
+            hamming_search(string, pattern, t, iupac)
+        }
+        EditPrefix(t) => {
+            let (edits, end_idx) = myers(string, pattern, false, iupac);
+            (edits <= t.get(pattern_len)).then(|| (pattern_len - edits, end_idx, 0))
+        }
+        EditSuffix(t) => {
+            let string_rev = string.iter().rev().copied().collect::<Vec<_>>();
+            let pattern_rev = pattern.iter().rev().copied().collect::<Vec<_>>();
+            let (edits, len) = myers(&string_rev, &pattern_rev, false, iupac);
+            (edits <= t.get(pattern_len)).then(|| (pattern_len - edits, string.len() - len, 0))
+        }
+        EditSearch(t) => {
+            let (edits, end_idx) = myers(string, pattern, true, iupac);
+            if edits > t.get(pattern_len) {
+                return None;
+            }
 
-    if matches >= threshold {
-        Some(matches)
-    } else {
-        None
+            // align backwards from the end of the match to find where it starts
+            let string_rev = string[..end_idx].iter().rev().copied().collect::<Vec<_>>();
+            let pattern_rev = pattern.iter().rev().copied().collect::<Vec<_>>();
+            let (_, len) = myers(&string_rev, &pattern_rev, false, iupac);
+            Some((pattern_len - edits, end_idx - len, end_idx))
+        }
+        GlobalAln(identity) => aligner
+            .as_mut()
+            .unwrap()
+            .align(string, pattern, identity, identity)
+            .map(|(m, _, end_idx)| (m, end_idx, 0)),
+        LocalAln { identity, overlap } => aligner
+            .as_mut()
+            .unwrap()
+            .align(string, pattern, identity, overlap),
+        PrefixAln { identity, overlap } => {
+            let additional = ((1.0 - identity).max(0.0) * (pattern_len as f64)).ceil() as usize;
+            let len = string.len().min(pattern_len + additional);
+            aligner
+                .as_mut()
+                .unwrap()
+                .align(&string[..len], pattern, identity, overlap)
+                .map(|(m, _, end_idx)| (m, end_idx, 0))
+        }
+        SuffixAln { identity, overlap } => {
+            let additional = ((1.0 - identity).max(0.0) * (pattern_len as f64)).ceil() as usize;
+            let len = string.len().min(pattern_len + additional);
+            aligner
+                .as_mut()
+                .unwrap()
+                .align(&string[string.len() - len..], pattern, identity, overlap)
+                .map(|(m, start_idx, _)| (m, string.len() - len + start_idx, 0))
+        }
     }
 }
 
-fn hamming_search(a: &[u8], b: &[u8], threshold: usize) -> Option<(usize, usize, usize)> {
+fn hamming_search(
+    a: &[u8],
+    b: &[u8],
+    threshold: usize,
+    iupac: bool,
+) -> Option<(usize, usize, usize)> {
     let mut best_match = None;
 
     for (i, w) in a.windows(b.len()).enumerate() {
-        if let Some(matches) = hamming(w, b, threshold) {
+        if let Some(matches) = hamming(w, b, threshold, iupac) {
             if let Some((best_matches, _, _)) = best_match {
                 if matches <= best_matches {
                     continue;
@@ -357,7 +750,116 @@ fn hamming_search(a: &[u8], b: &[u8], threshold: usize) -> Option<(usize, usize,
     best_match
 }
 
-trait Aligner {
+/// Aligners for each alignment-based match type, which are created when they are first used.
+#[derive(Default)]
+pub(crate) struct Aligners {
+    // the last aligner is always None, for match types that do not use alignment
+    aligners: [Option<Box<dyn Aligner>>; 5],
+}
+
+impl Aligners {
+    pub(crate) fn get(
+        &mut self,
+        match_type: MatchType,
+        len: usize,
+        iupac: bool,
+    ) -> &mut Option<Box<dyn Aligner>> {
+        use MatchType::*;
+        let i = match match_type {
+            GlobalAln(_) => 0,
+            LocalAln { .. } => 1,
+            PrefixAln { .. } => 2,
+            SuffixAln { .. } => 3,
+            _ => 4,
+        };
+        let aligner = &mut self.aligners[i];
+
+        if aligner.is_none() {
+            *aligner = if iupac {
+                new_aligner(match_type, len, iupac_matrix(), true)
+            } else {
+                new_aligner(match_type, len, NucMatrix::new_simple(1, -1), false)
+            };
+        }
+
+        aligner
+    }
+}
+
+fn new_aligner<M: Matrix + 'static>(
+    match_type: MatchType,
+    len: usize,
+    matrix: M,
+    iupac: bool,
+) -> Option<Box<dyn Aligner>> {
+    use MatchType::*;
+    match match_type {
+        GlobalAln(_) => Some(Box::new(GlobalLocalAligner::<M, false>::new(
+            len, matrix, iupac,
+        ))),
+        LocalAln { .. } => Some(Box::new(GlobalLocalAligner::<M, true>::new(
+            len, matrix, iupac,
+        ))),
+        PrefixAln { .. } => Some(Box::new(PrefixSuffixAligner::<M, true>::new(
+            len, matrix, iupac,
+        ))),
+        SuffixAln { .. } => Some(Box::new(PrefixSuffixAligner::<M, false>::new(
+            len, matrix, iupac,
+        ))),
+        _ => None,
+    }
+}
+
+/// Scoring matrix where IUPAC codes match if they have any nucleotide in common.
+///
+/// The nucleotide matrix cannot distinguish between all IUPAC codes, so the amino acid matrix is
+/// used instead.
+fn iupac_matrix() -> AAMatrix {
+    let mut matrix = AAMatrix::new_simple(1, -1);
+    for a in b'A'..=b'Z' {
+        for b in b'A'..=b'Z' {
+            matrix.set(a, b, if iupac_eq(a, b) { 1 } else { -1 });
+        }
+    }
+    matrix
+}
+
+/// Count the matching bases in an alignment, treating IUPAC codes as degenerate bases.
+///
+/// The CIGAR string is traced backwards from the end of the alignment, since the alignment's
+/// equality operations only indicate exact matches.
+fn count_iupac_matches(
+    cigar: &Cigar,
+    query: &[u8],
+    query_end: usize,
+    reference: &[u8],
+    reference_end: usize,
+) -> usize {
+    let mut i = query_end;
+    let mut j = reference_end;
+    let mut matches = 0;
+
+    for idx in (0..cigar.len()).rev() {
+        let OpLen { op, len } = cigar.get(idx);
+
+        match op {
+            Operation::M | Operation::Eq | Operation::X => {
+                for _ in 0..len {
+                    i -= 1;
+                    j -= 1;
+                    matches += iupac_eq(query[i], reference[j]) as usize;
+                }
+            }
+            Operation::I => i -= len,
+            Operation::D => j -= len,
+            _ => (),
+        }
+    }
+
+    matches
+}
+
+pub(crate) trait Aligner {
     fn align(
         &mut self,
         read: &[u8],
@@ -367,17 +869,18 @@ trait Aligner {
     ) -> Option<(usize, usize, usize)>;
 }
 
-struct GlobalLocalAligner<const LOCAL: bool> {
+struct GlobalLocalAligner<M: Matrix, const LOCAL: bool> {
     read_padded: PaddedBytes,
     pattern_padded: PaddedBytes,
-    matrix: NucMatrix,
+    matrix: M,
+    iupac: bool,
     // always store trace
     block: Block<true, LOCAL, LOCAL, false>,
     cigar: Cigar,
     len: usize,
 }
 
-impl<const LOCAL: bool> GlobalLocalAligner<LOCAL> {
+impl<M: Matrix, const LOCAL: bool> GlobalLocalAligner<M, LOCAL> {
     const MIN_SIZE: usize = 32;
     const MAX_SIZE: usize = 512;
     const GAPS: Gaps = Gaps {
@@ -385,10 +888,9 @@ impl<const LOCAL: bool> GlobalLocalAligner<LOCAL> {
         extend: -1,
     };
 
-    pub fn new(len: usize) -> Self {
-        let read_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-        let pattern_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-        let matrix = NucMatrix::new_simple(1, -1);
+    pub fn new(len: usize, matrix: M, iupac: bool) -> Self {
+        let read_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
+        let pattern_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
 
         let block = Block::<true, LOCAL, LOCAL, false>::new(len, len, Self::MAX_SIZE);
         let cigar = Cigar::new(len, len);
@@ -397,6 +899,7 @@ impl<const LOCAL: bool> GlobalLocalAligner<LOCAL> {
             read_padded,
             pattern_padded,
             matrix,
+            iupac,
             block,
             cigar,
             len,
@@ -405,8 +908,8 @@ impl<const LOCAL: bool> GlobalLocalAligner<LOCAL> {
 
     fn resize_if_needed(&mut self, len: usize) {
         if len > self.len {
-            self.read_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-            self.pattern_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
+            self.read_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
+            self.pattern_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
             self.block = Block::<true, LOCAL, LOCAL, false>::new(len, len, Self::MAX_SIZE);
             self.cigar = Cigar::new(len, len);
             self.len = len;
@@ -414,7 +917,7 @@ impl<const LOCAL: bool> GlobalLocalAligner<LOCAL> {
     }
 }
 
-impl<const LOCAL: bool> Aligner for GlobalLocalAligner<LOCAL> {
+impl<M: Matrix, const LOCAL: bool> Aligner for GlobalLocalAligner<M, LOCAL> {
     fn align(
         &mut self,
         read: &[u8],
@@ -430,9 +933,8 @@ impl<const LOCAL: bool> Aligner for GlobalLocalAligner<LOCAL> {
             .next_power_of_two()
             .min(Self::MAX_SIZE);
 
-        self.read_padded.set_bytes::<NucMatrix>(read, max_size);
-        self.pattern_padded
-            .set_bytes::<NucMatrix>(pattern, max_size);
+        self.read_padded.set_bytes::<M>(read, max_size);
+        self.pattern_padded.set_bytes::<M>(pattern, max_size);
 
         let min_size = if LOCAL { max_size } else { Self::MIN_SIZE };
 
@@ -454,6 +956,18 @@ impl<const LOCAL: bool> Aligner for GlobalLocalAligner<LOCAL> {
             &mut self.cigar,
         );
 
+        let iupac_matches = if self.iupac {
+            Some(count_iupac_matches(
+                &self.cigar,
+                pattern,
+                res.query_idx,
+                read,
+                res.reference_idx,
+            ))
+        } else {
+            None
+        };
+
         let mut matches = 0;
         let mut total = 0;
 
@@ -480,6 +994,7 @@ impl<const LOCAL: bool> Aligner for GlobalLocalAligner<LOCAL> {
             total += len;
         }
 
+        let matches = iupac_matches.unwrap_or(matches);
         let identity = (matches as f64) / (total as f64);
         let overlap = (matches as f64) / (pattern.len() as f64);
 
@@ -491,10 +1006,11 @@ impl<const LOCAL: bool> Aligner for GlobalLocalAligner<LOCAL> {
     }
 }
 
-struct PrefixSuffixAligner<const PREFIX: bool> {
+struct PrefixSuffixAligner<M: Matrix, const PREFIX: bool> {
     read_padded: PaddedBytes,
     pattern_padded: PaddedBytes,
-    matrix: NucMatrix,
+    matrix: M,
+    iupac: bool,
     // always store trace
     block1: Block<true, true, false, true>,  // X-drop
     block2: Block<true, false, false, true>, // no X-drop
@@ -502,17 +1018,16 @@ struct PrefixSuffixAligner<const PREFIX: bool> {
     len: usize,
 }
 
-impl<const PREFIX: bool> PrefixSuffixAligner<PREFIX> {
+impl<M: Matrix, const PREFIX: bool> PrefixSuffixAligner<M, PREFIX> {
     const MAX_SIZE: usize = 512;
     const GAPS: Gaps = Gaps {
         open: -2,
         extend: -1,
     };
 
-    pub fn new(len: usize) -> Self {
-        let read_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-        let pattern_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-        let matrix = NucMatrix::new_simple(1, -1);
+    pub fn new(len: usize, matrix: M, iupac: bool) -> Self {
+        let read_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
+        let pattern_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
 
         let block1 = Block::<true, true, false, true>::new(len, len, Self::MAX_SIZE);
         let block2 = Block::<true, false, false, true>::new(len, len, Self::MAX_SIZE);
@@ -522,6 +1037,7 @@ impl<const PREFIX: bool> PrefixSuffixAligner<PREFIX> {
             read_padded,
             pattern_padded,
             matrix,
+            iupac,
             block1,
             block2,
             cigar,
@@ -531,8 +1047,8 @@ impl<const PREFIX: bool> PrefixSuffixAligner<PREFIX> {
 
     fn resize_if_needed(&mut self, len: usize) {
         if len > self.len {
-            self.read_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
-            self.pattern_padded = PaddedBytes::new::<NucMatrix>(len, Self::MAX_SIZE);
+            self.read_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
+            self.pattern_padded = PaddedBytes::new::<M>(len, Self::MAX_SIZE);
             self.block1 = Block::<true, true, false, true>::new(len, len, Self::MAX_SIZE);
             self.block2 = Block::<true, false, false, true>::new(len, len, Self::MAX_SIZE);
             self.cigar = Cigar::new(len, len);
@@ -541,7 +1057,7 @@ impl<const PREFIX: bool> PrefixSuffixAligner<PREFIX> {
     }
 }
 
-impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
+impl<M: Matrix, const PREFIX: bool> Aligner for PrefixSuffixAligner<M, PREFIX> {
     fn align(
         &mut self,
         read: &[u8],
@@ -559,13 +1075,11 @@ impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
 
         if PREFIX {
             // reverse sequences to convert to aligning suffix
-            self.read_padded.set_bytes_rev::<NucMatrix>(read, max_size);
-            self.pattern_padded
-                .set_bytes_rev::<NucMatrix>(pattern, max_size);
+            self.read_padded.set_bytes_rev::<M>(read, max_size);
+            self.pattern_padded.set_bytes_rev::<M>(pattern, max_size);
         } else {
-            self.read_padded.set_bytes::<NucMatrix>(read, max_size);
-            self.pattern_padded
-                .set_bytes::<NucMatrix>(pattern, max_size);
+            self.read_padded.set_bytes::<M>(read, max_size);
+            self.pattern_padded.set_bytes::<M>(pattern, max_size);
         }
 
         // first align to get where the pattern starts in the read
@@ -581,6 +1095,8 @@ impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
         );
 
         let res = self.block1.res();
+        let mut cigar_end = (res.query_idx, res.reference_idx);
+        let mut realigned = false;
         self.block1.trace().cigar_eq(
             &self.pattern_padded,
             &self.read_padded,
@@ -604,14 +1120,12 @@ impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
             // get the overlapping prefix/suffix region
             if PREFIX {
                 self.read_padded
-                    .set_bytes::<NucMatrix>(&read[..read.len() - read_start_idx], max_size);
-                self.pattern_padded
-                    .set_bytes::<NucMatrix>(pattern, max_size);
+                    .set_bytes::<M>(&read[..read.len() - read_start_idx], max_size);
+                self.pattern_padded.set_bytes::<M>(pattern, max_size);
             } else {
                 self.read_padded
-                    .set_bytes_rev::<NucMatrix>(&read[read_start_idx..], max_size);
-                self.pattern_padded
-                    .set_bytes_rev::<NucMatrix>(pattern, max_size);
+                    .set_bytes_rev::<M>(&read[read_start_idx..], max_size);
+                self.pattern_padded.set_bytes_rev::<M>(pattern, max_size);
             }
 
             // align again with read and pattern switched and reversed so that end gaps in the read
@@ -626,6 +1140,8 @@ impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
             );
 
             let res = self.block2.res();
+            cigar_end = (res.query_idx, res.reference_idx);
+            realigned = true;
             self.block2.trace().cigar_eq(
                 &self.read_padded,
                 &self.pattern_padded,
@@ -647,6 +1163,22 @@ impl<const PREFIX: bool> Aligner for PrefixSuffixAligner<PREFIX> {
             total += len;
         }
 
+        if self.iupac {
+            // trace the CIGAR string with the same sequences that were aligned
+            let rev = |s: &[u8]| s.iter().rev().copied().collect::<Vec<_>>();
+            let (query, reference) = match (realigned, PREFIX) {
+                (false, true) => (rev(pattern), rev(read)),
+                (false, false) => (pattern.to_owned(), read.to_owned()),
+                (true, true) => (
+                    read[..read.len() - read_start_idx].to_owned(),
+                    pattern.to_owned(),
+                ),
+                (true, false) => (rev(&read[read_start_idx..]), rev(pattern)),
+            };
+            matches =
+                count_iupac_matches(&self.cigar, &query, cigar_end.0, &reference, cigar_end.1);
+        }
+
         let identity = (matches as f64) / (total as f64);
         let overlap = (matches as f64) / (pattern.len() as f64);
 
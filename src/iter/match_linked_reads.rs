@@ -0,0 +1,242 @@
+use crate::iter::match_any_reads::{match_pattern, Aligners};
+use crate::iter::*;
+use crate::seq_utils::*;
+
+pub struct MatchLinkedReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    label: Label,
+    new_labels: [Option<Label>; 5],
+    adapter5: FormatExpr,
+    match_type5: MatchType,
+    adapter3: FormatExpr,
+    match_type3: MatchType,
+    required: [bool; 2],
+    found_attrs: [Option<Attr>; 2],
+    // whether N in the read matches any base, if IUPAC codes are enabled
+    iupac: Option<bool>,
+}
+
+impl<R: Reads> MatchLinkedReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        adapter5: FormatExpr,
+        match_type5: MatchType,
+        adapter3: FormatExpr,
+        match_type3: MatchType,
+    ) -> Self {
+        use MatchType::*;
+        assert!(
+            matches!(
+                match_type5,
+                ExactPrefix
+                    | ExactSearch
+                    | HammingPrefix(_)
+                    | HammingSearch(_)
+                    | EditPrefix(_)
+                    | EditSearch(_)
+                    | PrefixAln { .. }
+                    | LocalAln { .. }
+            ),
+            "Only prefix or search match types can be used for the 5' adapter when matching linked adapters"
+        );
+        assert!(
+            matches!(
+                match_type3,
+                ExactSuffix
+                    | ExactSearch
+                    | HammingSuffix(_)
+                    | HammingSearch(_)
+                    | EditSuffix(_)
+                    | EditSearch(_)
+                    | SuffixAln { .. }
+                    | LocalAln { .. }
+            ),
+            "Only suffix or search match types can be used for the 3' adapter when matching linked adapters"
+        );
+
+        transform_expr.check_size(1, 5, "matching linked adapters");
+        transform_expr.check_same_str_type("matching linked adapters");
+
+        let mut new_labels = [None, None, None, None, None];
+        for (i, new_label) in new_labels.iter_mut().enumerate() {
+            *new_label = transform_expr.after()[i].clone().map(|l| match l {
+                LabelOrAttr::Label(l) => l,
+                _ => panic!("Expected type.label after the \"->\" in the transform expression when matching linked adapters"),
+            });
+        }
+
+        Self {
+            reads,
+            selector_expr,
+            label: transform_expr.before()[0].clone(),
+            new_labels,
+            adapter5,
+            match_type5,
+            adapter3,
+            match_type3,
+            required: [true, true],
+            found_attrs: [None, None],
+            iupac: None,
+        }
+    }
+
+    /// Set whether each adapter must be found.
+    ///
+    /// If an adapter is optional and it is not found, then its mapping is empty. At least one
+    /// adapter must be found for a match, even if both are optional.
+    #[must_use]
+    pub fn required(mut self, adapter5_required: bool, adapter3_required: bool) -> Self {
+        self.required = [adapter5_required, adapter3_required];
+        self
+    }
+
+    /// Set attributes to whether each adapter is found.
+    ///
+    /// Both attributes are set to false if there is no match.
+    #[must_use]
+    pub fn found(mut self, adapter5_attr: Option<Attr>, adapter3_attr: Option<Attr>) -> Self {
+        self.found_attrs = [adapter5_attr, adapter3_attr];
+        self
+    }
+
+    /// Treat IUPAC codes (e.g., `N`, `R`, or `W`) in the adapters and the read as degenerate bases.
+    ///
+    /// This is the same as [`MatchAnyReads::iupac`].
+    #[must_use]
+    pub fn iupac(mut self, read_n_matches: bool) -> Self {
+        self.iupac = Some(read_n_matches);
+        self
+    }
+}
+
+impl<R: Reads> Reads for MatchLinkedReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+        let mut aligners = Aligners::default();
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching linked adapters",
+                })?)
+            {
+                continue;
+            }
+
+            let format = |expr: &FormatExpr| {
+                expr.format(read, false).map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching linked adapters",
+                })
+            };
+            let adapter5 = format(&self.adapter5)?;
+            let adapter3 = format(&self.adapter3)?;
+
+            let string = read
+                .substring(self.label.str_type, self.label.label)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching linked adapters",
+                })?;
+
+            // IUPAC matching is symmetric, so Ns in the read are replaced if they should not match
+            let masked;
+            let string =
+                if self.iupac == Some(false) && string.iter().any(|&c| c == b'N' || c == b'n') {
+                    masked = mask_n(string);
+                    &masked
+                } else {
+                    string
+                };
+            let string_len = string.len();
+
+            let found5 = match_pattern(
+                self.match_type5,
+                string,
+                &adapter5,
+                self.iupac.is_some(),
+                aligners.get(self.match_type5, string_len * 2, self.iupac.is_some()),
+            )
+            .map(|(_, cut_pos1, cut_pos2)| self.match_type5.region(string_len, cut_pos1, cut_pos2));
+
+            // the 3' adapter is only searched for after the 5' adapter
+            let start3 = found5.map(|(_, end)| end).unwrap_or(0);
+            let found3 = if start3 == string_len {
+                None
+            } else {
+                match_pattern(
+                    self.match_type3,
+                    &string[start3..],
+                    &adapter3,
+                    self.iupac.is_some(),
+                    aligners.get(self.match_type3, string_len * 2, self.iupac.is_some()),
+                )
+                .map(|(_, cut_pos1, cut_pos2)| {
+                    let (start, end) =
+                        self.match_type3
+                            .region(string_len - start3, cut_pos1, cut_pos2);
+                    (start3 + start, start3 + end)
+                })
+            };
+
+            let matched = (found5.is_some() || !self.required[0])
+                && (found3.is_some() || !self.required[1])
+                && (found5.is_some() || found3.is_some());
+
+            for (attr, found) in self.found_attrs.iter().zip([found5, found3]) {
+                if let Some(attr) = attr {
+                    // panic to make borrow checker happy
+                    *read
+                        .data_mut(attr.str_type, attr.label, attr.attr)
+                        .unwrap_or_else(|e| panic!("Error matching linked adapters: {e}")) =
+                        Data::Bool(matched && found.is_some());
+                }
+            }
+
+            if !matched {
+                continue;
+            }
+
+            let (start5, end5) = found5.unwrap_or((0, 0));
+            let (start3, end3) = found3.unwrap_or((string_len, string_len));
+            let offset = read
+                .mapping(self.label.str_type, self.label.label)
+                .unwrap()
+                .start;
+            let str_mappings = read.str_mappings_mut(self.label.str_type).unwrap();
+            let intervals = [
+                (0, start5),
+                (start5, end5),
+                (end5, start3),
+                (start3, end3),
+                (end3, string_len),
+            ];
+
+            for (new_label, (start, end)) in self.new_labels.iter().zip(intervals) {
+                // panic to make borrow checker happy
+                str_mappings
+                    .add_mapping(
+                        new_label.as_ref().map(|l| l.label),
+                        offset + start,
+                        end - start,
+                    )
+                    .unwrap_or_else(|e| panic!("Error matching linked adapters: {e}"));
+            }
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::iter::*;
+
+pub struct MatchQualityReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    label: Label,
+    new_label1: Option<Label>,
+    new_label2: Option<Label>,
+    cutoff: u8,
+    end: End,
+    phred_offset: u8,
+}
+
+impl<R: Reads> MatchQualityReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        cutoff: u8,
+        end: End,
+        phred_offset: u8,
+    ) -> Self {
+        transform_expr.check_size(1, 2, "matching low quality bases");
+        transform_expr.check_same_str_type("matching low quality bases");
+
+        Self {
+            reads,
+            selector_expr,
+            label: transform_expr.before()[0].clone(),
+            new_label1: transform_expr.after()[0].clone().map(|l| match l {
+                LabelOrAttr::Label(l) => l,
+                _ => panic!("Expected type.label after the \"->\" in the transform expression when matching low quality bases"),
+            }),
+            new_label2: transform_expr.after()[1].clone().map(|l| match l {
+                LabelOrAttr::Label(l) => l,
+                _ => panic!("Expected type.label after the \"->\" in the transform expression when matching low quality bases"),
+            }),
+            cutoff,
+            end,
+            phred_offset,
+        }
+    }
+}
+
+impl<R: Reads> Reads for MatchQualityReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching low quality bases",
+                })?)
+            {
+                continue;
+            }
+
+            let qual = read
+                .substring_qual(self.label.str_type, self.label.label)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "matching low quality bases",
+                })?;
+
+            // reads without quality scores are treated as high quality
+            let trim_len = qual.map_or(0, |q| {
+                match_quality(q, self.cutoff, self.end, self.phred_offset)
+            });
+
+            read.cut(
+                self.label.str_type,
+                self.label.label,
+                self.new_label1.as_ref().map(|l| l.label),
+                self.new_label2.as_ref().map(|l| l.label),
+                EndIdx::from_end(self.end, trim_len),
+            )
+            .map_err(|e| Error::NameError {
+                source: e,
+                read: read.clone(),
+                context: "matching low quality bases",
+            })?;
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
+
+/// Get the number of low quality bases to trim from an end.
+///
+/// This is the same algorithm as BWA and cutadapt: the quality scores are subtracted from the
+/// cutoff and summed starting from the end, and the trim position is where the partial sum is
+/// maximized. The sum stops once it becomes negative.
+fn match_quality(qual: &[u8], cutoff: u8, end: End, phred_offset: u8) -> usize {
+    let mut sum = 0i32;
+    let mut max_sum = 0i32;
+    let mut max_len = 0;
+
+    let mut f = |(i, q): (usize, u8)| {
+        sum += (cutoff as i32) - ((q as i32) - (phred_offset as i32));
+
+        if sum < 0 {
+            return false;
+        }
+
+        if sum > max_sum {
+            max_sum = sum;
+            max_len = i + 1;
+        }
+
+        true
+    };
+
+    match end {
+        Left => {
+            let _ = qual.iter().cloned().enumerate().all(&mut f);
+        }
+        Right => {
+            let _ = qual.iter().rev().cloned().enumerate().all(&mut f);
+        }
+    }
+
+    max_len
+}
@@ -0,0 +1,132 @@
+use crate::iter::*;
+
+pub struct QualityStatsReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    label: Label,
+    attr: Option<Attr>,
+    stat: QualStat,
+    phred_offset: u8,
+}
+
+impl<R: Reads> QualityStatsReads<R> {
+    pub fn new(
+        reads: R,
+        selector_expr: SelectorExpr,
+        transform_expr: TransformExpr,
+        stat: QualStat,
+        phred_offset: u8,
+    ) -> Self {
+        transform_expr.check_size(1, 1, "computing quality statistics");
+        transform_expr.check_same_str_type("computing quality statistics");
+
+        Self {
+            reads,
+            selector_expr,
+            label: transform_expr.before()[0].clone(),
+            attr: transform_expr.after()[0].clone().map(|a| match a {
+                LabelOrAttr::Attr(a) => a,
+                _ => panic!("Expected type.label.attr after the \"->\" in the transform expression when computing quality statistics"),
+            }),
+            stat,
+            phred_offset,
+        }
+    }
+}
+
+impl<R: Reads> Reads for QualityStatsReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut reads = self.reads.next_chunk()?;
+
+        for read in reads.iter_mut() {
+            if !(self
+                .selector_expr
+                .matches(read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "computing quality statistics",
+                })?)
+            {
+                continue;
+            }
+
+            if let Some(attr) = &self.attr {
+                let qual = read
+                    .substring_qual(self.label.str_type, self.label.label)
+                    .map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context: "computing quality statistics",
+                    })?;
+
+                // reads without quality scores do not have a statistic
+                let data = match qual {
+                    Some(qual) => self.stat.compute(qual, self.phred_offset),
+                    None => Data::Bool(false),
+                };
+
+                // panic to make borrow checker happy
+                *read
+                    .data_mut(attr.str_type, attr.label, attr.attr)
+                    .unwrap_or_else(|e| panic!("Error computing quality statistics: {e}")) = data;
+            }
+        }
+
+        Ok(reads)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
+
+impl QualStat {
+    fn compute(&self, qual: &[u8], phred_offset: u8) -> Data {
+        use QualStat::*;
+        let phred = qual
+            .iter()
+            .map(move |&q| q.saturating_sub(phred_offset) as usize);
+
+        match *self {
+            Mean => Data::Float(mean(phred)),
+            Min => Data::UInt(phred.min().unwrap_or(0)),
+            ExpectedErrors => {
+                Data::Float(phred.map(|q| 10f64.powf(-(q as f64) / 10.0)).sum::<f64>())
+            }
+            FracBelow(cutoff) => {
+                if qual.is_empty() {
+                    Data::Float(0.0)
+                } else {
+                    let below = phred.filter(|&q| q < (cutoff as usize)).count();
+                    Data::Float((below as f64) / (qual.len() as f64))
+                }
+            }
+            MinWindowMean(window) => {
+                let window = window.max(1);
+
+                if qual.len() <= window {
+                    Data::Float(mean(phred))
+                } else {
+                    let phred = phred.collect::<Vec<_>>();
+                    let min_sum = phred
+                        .windows(window)
+                        .map(|w| w.iter().sum::<usize>())
+                        .min()
+                        .unwrap();
+                    Data::Float((min_sum as f64) / (window as f64))
+                }
+            }
+        }
+    }
+}
+
+fn mean(phred: impl Iterator<Item = usize>) -> f64 {
+    let (sum, len) = phred.fold((0, 0), |(sum, len), q| (sum + q, len + 1));
+
+    if len == 0 {
+        0.0
+    } else {
+        (sum as f64) / (len as f64)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::inline_string::*;
+use crate::iter::*;
+
+pub struct SplitReads<R: Reads> {
+    reads: R,
+    selector_expr: SelectorExpr,
+    segments: Segments,
+}
+
+enum Segments {
+    Labels(Vec<Label>),
+    // boxed because the cache of indexed labels is large
+    Indexed(StrType, Box<IndexedLabels>),
+}
+
+impl<R: Reads> SplitReads<R> {
+    pub fn new(reads: R, selector_expr: SelectorExpr, labels: Vec<Label>) -> Self {
+        assert!(
+            labels.len() <= MAX_SPLITS,
+            "Cannot split a read into more than {MAX_SPLITS} reads"
+        );
+
+        Self {
+            reads,
+            selector_expr,
+            segments: Segments::Labels(labels),
+        }
+    }
+
+    pub fn new_indexed(reads: R, selector_expr: SelectorExpr, label: Label) -> Self {
+        Self {
+            reads,
+            selector_expr,
+            segments: Segments::Indexed(label.str_type, Box::new(IndexedLabels::new(label.label))),
+        }
+    }
+}
+
+impl<R: Reads> Reads for SplitReads<R> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let reads = self.reads.next_chunk()?;
+        let mut res = Vec::with_capacity(reads.len());
+
+        for mut read in reads {
+            let idx = read.first_idx();
+            // record indexes that do not fit after renumbering are treated like too many splits
+            let split_idx = |read: &Read, i: usize| {
+                idx.checked_mul(MAX_SPLITS)
+                    .and_then(|idx| idx.checked_add(i))
+                    .ok_or_else(|| Error::TooManySplits {
+                        max: MAX_SPLITS,
+                        read: read.clone(),
+                        context: "splitting reads",
+                    })
+            };
+
+            if !(self
+                .selector_expr
+                .matches(&read)
+                .map_err(|e| Error::NameError {
+                    source: e,
+                    read: read.clone(),
+                    context: "splitting reads",
+                })?)
+            {
+                // keep the record indexes of all reads consistent
+                read.set_idx(split_idx(&read, 0)?);
+                res.push(read);
+                continue;
+            }
+
+            let mut split = |i: usize, str_type: StrType, label: InlineString| -> Result<()> {
+                // empty segments do not produce reads, but they still count towards the index
+                if read
+                    .mapping(str_type, label)
+                    .map_err(|e| Error::NameError {
+                        source: e,
+                        read: read.clone(),
+                        context: "splitting reads",
+                    })?
+                    .len
+                    > 0
+                {
+                    let new_idx = split_idx(&read, i)?;
+                    res.push(read.split(str_type, label, new_idx).map_err(|e| {
+                        Error::NameError {
+                            source: e,
+                            read: read.clone(),
+                            context: "splitting reads",
+                        }
+                    })?);
+                }
+                Ok(())
+            };
+
+            match &self.segments {
+                Segments::Labels(labels) => {
+                    for (i, l) in labels.iter().enumerate() {
+                        split(i, l.str_type, l.label)?;
+                    }
+                }
+                Segments::Indexed(str_type, indexed_labels) => {
+                    let str_mappings =
+                        read.str_mappings(*str_type)
+                            .ok_or_else(|| Error::NameError {
+                                source: NameError::NotInRead(Name::StrType(*str_type)),
+                                read: read.clone(),
+                                context: "splitting reads",
+                            })?;
+                    let labels = (0..=MAX_SPLITS)
+                        .map(|i| indexed_labels.get(i))
+                        .take_while(|&label| str_mappings.mapping(label).is_some())
+                        .collect::<Vec<_>>();
+
+                    if labels.len() > MAX_SPLITS {
+                        return Err(Error::TooManySplits {
+                            max: MAX_SPLITS,
+                            read: read.clone(),
+                            context: "splitting reads",
+                        });
+                    }
+
+                    for (i, label) in labels.into_iter().enumerate() {
+                        split(i, *str_type, label)?;
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.reads.finish()
+    }
+}
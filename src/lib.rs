@@ -17,8 +17,9 @@
 //! on reads in a chain, and these are executed in order when you call
 //! [`run()`](Reads::run) or [`run_with_threads()`](Reads::run_with_threads).
 //!
-//! See [`fastq`] for functions for reading fastq records and
-//! see [`Reads`] for all the supported read iterator operations.
+//! See [`fastq`] for functions for reading fastq records, [`fasta`] for functions for reading
+//! fasta records, [`sam`] for functions for reading unaligned SAM/BAM records, and see [`Reads`]
+//! for all the supported read iterator operations.
 //!
 //! ## Read structure
 //! Here's an example fastq record:
@@ -71,6 +72,11 @@
 //! * `sel!()`: select all reads
 //! * `sel!(seq1.adapter)`: select only reads with the `adapter` mapping in its sequence
 //! * `sel!(seq1.adapter & !seq1.*.discard)`: arbitrary boolean expression!
+//! * `sel!(len(seq1.bc) >= 9 & seq1.*.sample == "S1")`: compare mapping lengths, attributes,
+//! mapping substrings, and string or number literals with `==`, `!=`, `<`, `<=`, `>`, or `>=`
+//! * `sel!(name1.* ~ ":N:")`: check whether a mapping or attribute matches a regex
+//! * `sel!(seq1.bc in "whitelist.txt")`: check whether a mapping or attribute is in a list of
+//! strings loaded from a file (one per line) or a patterns YAML file
 //!
 //! ## Transform expressions
 //! Transform expressions allow you to specify the names of the inputs and outputs
@@ -82,23 +88,33 @@
 //! and they are similar to Rust's formatting syntax. For example, you can use `"{seq1.a}_{seq1.b}"`
 //! to concatenate the substrings corresponding to mappings `a` and `b`, separated by an
 //! underscore. A string can also be repeated, like `"{'A'; 4}"`, which results in `AAAA`.
+//! Float attributes can be formatted with a fixed number of digits after the decimal point,
+//! like `"{seq1.*.ee:.2}"`.
+//! Strings can also be transformed with functions like `"{revcomp(seq2.bc)}"`,
+//! `"{upper(seq1.a)}"`, `"{lower(seq1.a)}"`, and `"{len(seq1.insert)}"`, or sliced like
+//! `"{seq1.umi[0..8]}"`.
 //!
 //! Format expressions are useful for rearranging and modifying strings.
 //! They also preserve quality scores, making rearranging regions in a read easy.
 
 pub mod errors;
 pub mod expr;
+pub mod fasta;
 pub mod fastq;
 pub mod iter;
 pub mod patterns;
 pub mod read;
+pub mod sam;
 
 mod inline_string;
 mod parse_utils;
+mod seq_utils;
 
 // commonly used functions and types
 
+pub use crate::fasta::*;
 pub use crate::fastq::*;
 pub use crate::iter::*;
 pub use crate::patterns::*;
 pub use crate::read::*;
+pub use crate::sam::*;
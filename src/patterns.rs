@@ -2,10 +2,13 @@ use serde::{Deserialize, Serialize};
 use serde_yaml;
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufReader, Read as _};
 
 use crate::errors::*;
 use crate::expr::FormatExpr;
 use crate::inline_string::*;
+use crate::iter::{MatchType, Threshold};
+use crate::parse_utils::*;
 use crate::read::*;
 
 pub struct Patterns {
@@ -24,6 +27,7 @@ impl Patterns {
                 .map(|v| Pattern {
                     expr: v,
                     attrs: Vec::new(),
+                    match_type: PatternMatchType::default(),
                 })
                 .collect(),
         }
@@ -36,29 +40,51 @@ impl Patterns {
                 source: Box::new(e),
             })?;
 
-        let pattern_name = Some(InlineString::new(patterns.name.as_bytes()));
+        let pattern_name = Some(InlineString::new(
+            check_valid_name(patterns.name.as_bytes()).ok_or_else(|| Error::InvalidName {
+                string: patterns.name.clone(),
+                context: "pattern name".to_owned(),
+            })?,
+        ));
 
         let attr_names = patterns.patterns[0]
             .attrs
             .iter()
-            .map(|(k, _)| InlineString::new(k.as_bytes()))
-            .collect::<BTreeSet<_>>();
+            .map(|(k, _)| {
+                Ok(InlineString::new(
+                    check_valid_name(k.as_bytes()).ok_or_else(|| Error::InvalidName {
+                        string: k.clone(),
+                        context: "pattern attributes".to_owned(),
+                    })?,
+                ))
+            })
+            .collect::<Result<BTreeSet<_>>>()?;
 
         let patterns = patterns
             .patterns
             .into_iter()
-            .map(|PatternSchema { pattern, attrs }| {
-                let expr = FormatExpr::new(pattern.as_bytes())?;
-                let attrs = attrs
-                    .iter()
-                    .map(|(k, v)| {
-                        let s = InlineString::new(k.as_bytes());
-                        assert!(attr_names.contains(&s));
-                        v.to_data()
+            .map(
+                |PatternSchema {
+                     pattern,
+                     attrs,
+                     match_type,
+                 }| {
+                    let expr = FormatExpr::new(pattern.as_bytes())?;
+                    let attrs = attrs
+                        .iter()
+                        .map(|(k, v)| {
+                            let s = InlineString::new(k.as_bytes());
+                            assert!(attr_names.contains(&s));
+                            v.to_data()
+                        })
+                        .collect::<Vec<_>>();
+                    Ok(Pattern {
+                        expr,
+                        attrs,
+                        match_type,
                     })
-                    .collect::<Vec<_>>();
-                Ok(Pattern { expr, attrs })
-            })
+                },
+            )
             .collect::<Result<Vec<_>>>()?;
 
         let attr_names = attr_names.into_iter().collect::<Vec<_>>();
@@ -70,6 +96,193 @@ impl Patterns {
         })
     }
 
+    /// Create patterns from a list with one pattern per line.
+    ///
+    /// Only the first whitespace-separated field of each line is used, and empty lines are
+    /// skipped.
+    pub fn from_list(name: impl AsRef<str>, list: impl AsRef<[u8]>) -> Result<Self> {
+        let patterns = list
+            .as_ref()
+            .split(|&c| c == b'\n')
+            .filter_map(trim_ascii_whitespace)
+            .map(|line| {
+                let pattern = line.split(|c| c.is_ascii_whitespace()).next().unwrap();
+                Ok(Pattern {
+                    expr: FormatExpr::new(pattern)?,
+                    attrs: Vec::new(),
+                    match_type: PatternMatchType::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pattern_name: Some(valid_name(name.as_ref(), "pattern name")?),
+            attr_names: Vec::new(),
+            patterns,
+        })
+    }
+
+    /// Create patterns from a delimited table, like a CSV or TSV file.
+    ///
+    /// The first line must be a header with the name of each column. The patterns are in the
+    /// column named `pattern_column`, and all other columns are stored as string attributes.
+    /// Fields are not allowed to be quoted.
+    pub fn from_table(
+        name: impl AsRef<str>,
+        table: impl AsRef<[u8]>,
+        delimiter: u8,
+        pattern_column: impl AsRef<str>,
+    ) -> Result<Self> {
+        let table = table.as_ref();
+        let mut lines = table
+            .split(|&c| c == b'\n')
+            .filter_map(trim_ascii_whitespace);
+        fn split(line: &[u8], delimiter: u8) -> Vec<&[u8]> {
+            line.split(|&c| c == delimiter)
+                .map(|field| trim_ascii_whitespace(field).unwrap_or(b""))
+                .collect()
+        }
+
+        let header = split(lines.next().unwrap_or(b""), delimiter);
+        let pattern_idx = header
+            .iter()
+            .position(|&c| c == pattern_column.as_ref().as_bytes())
+            .ok_or_else(|| Error::Parse {
+                string: utf8(&header.join(&delimiter)),
+                context: "pattern table header".to_owned(),
+                reason: "missing pattern column",
+            })?;
+        let attr_names = header
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != pattern_idx)
+            .map(|(_, c)| valid_name(&utf8(c), "pattern attributes"))
+            .collect::<Result<Vec<_>>>()?;
+
+        let patterns = lines
+            .map(|line| {
+                let fields = split(line, delimiter);
+                if fields.len() != header.len() {
+                    return Err(Error::Parse {
+                        string: utf8(line),
+                        context: "pattern table".to_owned(),
+                        reason: "the number of fields does not match the header",
+                    });
+                }
+
+                Ok(Pattern {
+                    expr: FormatExpr::new(fields[pattern_idx])?,
+                    attrs: fields
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| i != pattern_idx)
+                        .map(|(_, f)| Data::Bytes(f.to_vec()))
+                        .collect(),
+                    match_type: PatternMatchType::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pattern_name: Some(valid_name(name.as_ref(), "pattern name")?),
+            attr_names,
+            patterns,
+        })
+    }
+
+    /// Create patterns from FASTA records.
+    ///
+    /// The first whitespace-separated field of each record's header is stored as the string
+    /// attribute `header_attr`. Sequences can span multiple lines.
+    pub fn from_fasta(
+        name: impl AsRef<str>,
+        fasta: impl AsRef<[u8]>,
+        header_attr: impl AsRef<str>,
+    ) -> Result<Self> {
+        let mut records: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+
+        for line in fasta
+            .as_ref()
+            .split(|&c| c == b'\n')
+            .filter_map(trim_ascii_whitespace)
+        {
+            if let Some(header) = line.strip_prefix(b">") {
+                let header = header.split(|c| c.is_ascii_whitespace()).next().unwrap();
+                records.push((header.to_owned(), Vec::new()));
+            } else if let Some((_, seq)) = records.last_mut() {
+                seq.extend_from_slice(line);
+            } else {
+                return Err(Error::Parse {
+                    string: utf8(line),
+                    context: "pattern FASTA".to_owned(),
+                    reason: "expected a header starting with '>'",
+                });
+            }
+        }
+
+        let patterns = records
+            .into_iter()
+            .map(|(header, seq)| {
+                Ok(Pattern {
+                    expr: FormatExpr::new(&seq)?,
+                    attrs: vec![Data::Bytes(header)],
+                    match_type: PatternMatchType::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pattern_name: Some(valid_name(name.as_ref(), "pattern name")?),
+            attr_names: vec![valid_name(header_attr.as_ref(), "pattern attributes")?],
+            patterns,
+        })
+    }
+
+    /// Load patterns from a delimited table file, which can be gzip compressed (`.gz`).
+    ///
+    /// See [`Patterns::from_table`] for the format.
+    pub fn from_table_file(
+        name: impl AsRef<str>,
+        file: impl AsRef<str>,
+        delimiter: u8,
+        pattern_column: impl AsRef<str>,
+    ) -> Result<Self> {
+        let bytes = read_file(file.as_ref())?;
+        Self::from_table(name, bytes, delimiter, pattern_column)
+    }
+
+    /// Load patterns from a file, which can be gzip compressed (`.gz`).
+    ///
+    /// The format is determined by the file extension:
+    /// * `.yaml` or `.yml`: patterns YAML, which specifies its own name, so `name` is ignored
+    /// * `.csv` or `.tsv`: a table where the column named `pattern_column` contains the patterns
+    /// * `.fa`, `.fasta`, or `.fna`: FASTA records, with headers stored in the `header` attribute
+    /// * anything else: a list with one pattern per line
+    ///
+    /// `pattern_column` is only used for tables.
+    pub fn from_file(
+        name: impl AsRef<str>,
+        file: impl AsRef<str>,
+        pattern_column: impl AsRef<str>,
+    ) -> Result<Self> {
+        let file = file.as_ref();
+        let path = file.strip_suffix(".gz").unwrap_or(file);
+        let ext = path.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+
+        match ext {
+            "csv" => return Self::from_table_file(name, file, b',', pattern_column),
+            "tsv" => return Self::from_table_file(name, file, b'\t', pattern_column),
+            _ => (),
+        }
+
+        let bytes = read_file(file)?;
+        match ext {
+            "yaml" | "yml" => Self::from_yaml(&bytes),
+            "fa" | "fasta" | "fna" => Self::from_fasta(name, &bytes, "header"),
+            _ => Self::from_list(name, &bytes),
+        }
+    }
+
     pub fn pattern_name(&self) -> Option<InlineString> {
         self.pattern_name
     }
@@ -83,9 +296,201 @@ impl Patterns {
     }
 }
 
+/// Read a whole file, which can be gzip compressed (`.gz`).
+fn read_file(file: &str) -> Result<Vec<u8>> {
+    let file_err = |e: std::io::Error| Error::FileIo {
+        file: file.to_owned(),
+        source: Box::new(e),
+    };
+
+    let f = std::fs::File::open(file).map_err(file_err)?;
+    let mut bytes = Vec::new();
+
+    if file.ends_with(".gz") {
+        flate2::read::MultiGzDecoder::new(f).read_to_end(&mut bytes)
+    } else {
+        BufReader::new(f).read_to_end(&mut bytes)
+    }
+    .map_err(file_err)?;
+
+    Ok(bytes)
+}
+
+fn valid_name(name: &str, context: &str) -> Result<InlineString> {
+    Ok(InlineString::new(
+        check_valid_name(name.as_bytes()).ok_or_else(|| Error::InvalidName {
+            string: name.to_owned(),
+            context: context.to_owned(),
+        })?,
+    ))
+}
+
 pub struct Pattern {
     pub expr: FormatExpr,
     pub attrs: Vec<Data>,
+    pub match_type: PatternMatchType,
+}
+
+/// Optional overrides for the operation-level match type of a pattern.
+///
+/// Any parameters that are not overridden are taken from the operation-level match type.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PatternMatchType {
+    match_type: Option<MatchTypeName>,
+    max_mismatches: Option<usize>,
+    identity: Option<f64>,
+    overlap: Option<f64>,
+}
+
+impl PatternMatchType {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Get the match type for a pattern, given the operation-level match type.
+    ///
+    /// For the Hamming-distance-based match types, `max_mismatches` is converted to a threshold
+    /// on the number of matching bases, so the length of the pattern is required.
+    pub fn get(
+        &self,
+        default: MatchType,
+        pattern_len: usize,
+    ) -> std::result::Result<MatchType, &'static str> {
+        use MatchType::*;
+        use MatchTypeName as N;
+
+        let name = self.match_type.unwrap_or_else(|| default.into());
+
+        if self.max_mismatches.is_some()
+            && !matches!(
+                name,
+                N::Hamming
+                    | N::HammingPrefix
+                    | N::HammingSuffix
+                    | N::HammingSearch
+                    | N::EditPrefix
+                    | N::EditSuffix
+                    | N::EditSearch
+            )
+        {
+            return Err(
+                "max_mismatches can only be used with Hamming or edit distance match types",
+            );
+        }
+        if self.identity.is_some()
+            && !matches!(
+                name,
+                N::GlobalAln | N::LocalAln | N::PrefixAln | N::SuffixAln
+            )
+        {
+            return Err("identity can only be used with alignment match types");
+        }
+        if self.overlap.is_some() && !matches!(name, N::LocalAln | N::PrefixAln | N::SuffixAln) {
+            return Err(
+                "overlap can only be used with local, prefix, or suffix alignment match types",
+            );
+        }
+
+        let hamming = || match (self.max_mismatches, default) {
+            (Some(m), _) => Ok(Threshold::Count(pattern_len.saturating_sub(m))),
+            (None, Hamming(t) | HammingPrefix(t) | HammingSuffix(t) | HammingSearch(t)) => Ok(t),
+            _ => Err("max_mismatches is required for Hamming distance match types"),
+        };
+        let edits = || match (self.max_mismatches, default) {
+            (Some(m), _) => Ok(Threshold::Count(m)),
+            (None, EditPrefix(t) | EditSuffix(t) | EditSearch(t)) => Ok(t),
+            _ => Err("max_mismatches is required for edit distance match types"),
+        };
+        let identity = || match (self.identity, default) {
+            (Some(i), _) => Ok(i),
+            (
+                None,
+                GlobalAln(identity)
+                | LocalAln { identity, .. }
+                | PrefixAln { identity, .. }
+                | SuffixAln { identity, .. },
+            ) => Ok(identity),
+            _ => Err("identity is required for alignment match types"),
+        };
+        let overlap = || match (self.overlap, default) {
+            (Some(o), _) => Ok(o),
+            (
+                None,
+                LocalAln { overlap, .. } | PrefixAln { overlap, .. } | SuffixAln { overlap, .. },
+            ) => Ok(overlap),
+            _ => Err("overlap is required for local, prefix, or suffix alignment match types"),
+        };
+
+        Ok(match name {
+            N::Exact => Exact,
+            N::ExactPrefix => ExactPrefix,
+            N::ExactSuffix => ExactSuffix,
+            N::ExactSearch => ExactSearch,
+            N::Hamming => Hamming(hamming()?),
+            N::HammingPrefix => HammingPrefix(hamming()?),
+            N::HammingSuffix => HammingSuffix(hamming()?),
+            N::HammingSearch => HammingSearch(hamming()?),
+            N::EditPrefix => EditPrefix(edits()?),
+            N::EditSuffix => EditSuffix(edits()?),
+            N::EditSearch => EditSearch(edits()?),
+            N::GlobalAln => GlobalAln(identity()?),
+            N::LocalAln => LocalAln {
+                identity: identity()?,
+                overlap: overlap()?,
+            },
+            N::PrefixAln => PrefixAln {
+                identity: identity()?,
+                overlap: overlap()?,
+            },
+            N::SuffixAln => SuffixAln {
+                identity: identity()?,
+                overlap: overlap()?,
+            },
+        })
+    }
+}
+
+/// Names of match types, without their parameters.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum MatchTypeName {
+    Exact,
+    ExactPrefix,
+    ExactSuffix,
+    ExactSearch,
+    Hamming,
+    HammingPrefix,
+    HammingSuffix,
+    HammingSearch,
+    EditPrefix,
+    EditSuffix,
+    EditSearch,
+    GlobalAln,
+    LocalAln,
+    PrefixAln,
+    SuffixAln,
+}
+
+impl From<MatchType> for MatchTypeName {
+    fn from(match_type: MatchType) -> Self {
+        use MatchType::*;
+        match match_type {
+            Exact => Self::Exact,
+            ExactPrefix => Self::ExactPrefix,
+            ExactSuffix => Self::ExactSuffix,
+            ExactSearch => Self::ExactSearch,
+            Hamming(_) => Self::Hamming,
+            HammingPrefix(_) => Self::HammingPrefix,
+            HammingSuffix(_) => Self::HammingSuffix,
+            HammingSearch(_) => Self::HammingSearch,
+            EditPrefix(_) => Self::EditPrefix,
+            EditSuffix(_) => Self::EditSuffix,
+            EditSearch(_) => Self::EditSearch,
+            GlobalAln(_) => Self::GlobalAln,
+            LocalAln { .. } => Self::LocalAln,
+            PrefixAln { .. } => Self::PrefixAln,
+            SuffixAln { .. } => Self::SuffixAln,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -94,17 +499,93 @@ struct PatternsSchema {
     pub patterns: Vec<PatternSchema>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "serde_yaml::Mapping", into = "serde_yaml::Mapping")]
 struct PatternSchema {
     pub pattern: String,
-    #[serde(flatten)]
     pub attrs: BTreeMap<String, DataSchema>,
+    pub match_type: PatternMatchType,
 }
 
-#[derive(Serialize, Deserialize)]
+// `#[serde(flatten)]` does not preserve YAML tags like `!UInt`, so the attributes are converted
+// from a YAML mapping manually, after removing the reserved match type fields
+impl TryFrom<serde_yaml::Mapping> for PatternSchema {
+    type Error = serde_yaml::Error;
+
+    fn try_from(mut mapping: serde_yaml::Mapping) -> std::result::Result<Self, Self::Error> {
+        let pattern = serde_yaml::from_value(
+            mapping
+                .remove("pattern")
+                .ok_or_else(|| <Self::Error as serde::de::Error>::missing_field("pattern"))?,
+        )?;
+
+        fn remove<T: serde::de::DeserializeOwned>(
+            mapping: &mut serde_yaml::Mapping,
+            field: &str,
+        ) -> std::result::Result<Option<T>, serde_yaml::Error> {
+            mapping
+                .remove(field)
+                .map(serde_yaml::from_value)
+                .transpose()
+        }
+        let match_type = PatternMatchType {
+            match_type: remove(&mut mapping, "match_type")?,
+            max_mismatches: remove(&mut mapping, "max_mismatches")?,
+            identity: remove(&mut mapping, "identity")?,
+            overlap: remove(&mut mapping, "overlap")?,
+        };
+
+        let attrs = mapping
+            .into_iter()
+            .map(|(k, v)| Ok((serde_yaml::from_value(k)?, serde_yaml::from_value(v)?)))
+            .collect::<std::result::Result<_, Self::Error>>()?;
+
+        Ok(Self {
+            pattern,
+            attrs,
+            match_type,
+        })
+    }
+}
+
+impl From<PatternSchema> for serde_yaml::Mapping {
+    fn from(schema: PatternSchema) -> Self {
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert("pattern".into(), schema.pattern.into());
+        let PatternMatchType {
+            match_type,
+            max_mismatches,
+            identity,
+            overlap,
+        } = schema.match_type;
+        [
+            (
+                "match_type",
+                match_type.map(|m| serde_yaml::to_value(m).unwrap()),
+            ),
+            ("max_mismatches", max_mismatches.map(|m| m.into())),
+            ("identity", identity.map(|i| i.into())),
+            ("overlap", overlap.map(|o| o.into())),
+        ]
+        .into_iter()
+        .for_each(|(k, v)| {
+            if let Some(v) = v {
+                mapping.insert(k.into(), v);
+            }
+        });
+        schema.attrs.into_iter().for_each(|(k, v)| {
+            mapping.insert(k.into(), serde_yaml::to_value(v).unwrap());
+        });
+        mapping
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 enum DataSchema {
     Bool(bool),
     UInt(usize),
+    Int(isize),
+    Float(f64),
     String(String),
 }
 
@@ -113,7 +594,54 @@ impl DataSchema {
         match self {
             DataSchema::Bool(x) => Data::Bool(*x),
             DataSchema::UInt(x) => Data::UInt(*x),
+            DataSchema::Int(x) => Data::Int(*x),
+            DataSchema::Float(x) => Data::Float(*x),
             DataSchema::String(x) => Data::Bytes(x.as_bytes().to_owned()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn gzipped_sample_sheet() {
+        let file = std::env::temp_dir().join(format!("samples_{}.csv.gz", std::process::id()));
+        let file = file.to_str().unwrap();
+        let mut w = GzEncoder::new(std::fs::File::create(file).unwrap(), Compression::default());
+        w.write_all(b"sample,i7,i5\ns1,ACGTACGT,GGGG\ns2, CCCCAAAA ,TTTT\n")
+            .unwrap();
+        w.finish().unwrap();
+
+        let patterns = Patterns::from_file("idx", file, "i7").unwrap();
+        let table_patterns = Patterns::from_table_file("idx", file, b',', "i5").unwrap();
+        let missing = Patterns::from_file("idx", file, "pattern");
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(patterns.pattern_name(), Some(InlineString::new(b"idx")));
+        assert_eq!(
+            patterns.attr_names(),
+            [InlineString::new(b"sample"), InlineString::new(b"i5")]
+        );
+        let literals = patterns
+            .patterns()
+            .iter()
+            .map(|p| p.expr.literal().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(literals, [b"ACGTACGT".to_vec(), b"CCCCAAAA".to_vec()]);
+        assert!(
+            patterns.patterns()[1].attrs
+                == [Data::Bytes(b"s2".to_vec()), Data::Bytes(b"TTTT".to_vec())]
+        );
+
+        assert_eq!(
+            table_patterns.patterns()[0].expr.literal(),
+            Some(b"GGGG".to_vec())
+        );
+        assert!(missing.is_err());
+    }
+}
@@ -28,14 +28,14 @@ pub enum End {
 ///
 /// Types like `Name` or `Seq` refer to the corresponding line in a fastq record.
 /// Each Read contains multiple different strings of different types.
+///
+/// Every type is numbered starting from 1 (e.g., `seq1`, `seq2`, `seq3`, etc.), so a read can
+/// have any number of sequence segments.
 #[derive(Debug, Clone, Copy, PartialEq, Hash)]
 pub enum StrType {
-    Name1,
-    Seq1,
-    Name2,
-    Seq2,
-    Index1,
-    Index2,
+    Name(usize),
+    Seq(usize),
+    Index(usize),
 }
 
 /// A fastq read.
@@ -47,6 +47,9 @@ pub struct Read {
     str_mappings: Vec<(StrType, StrMappings)>,
 }
 
+/// The name, sequence, and quality scores (if any) of a fastq record.
+pub type FastqRecord<'a> = (&'a [u8], &'a [u8], Option<&'a [u8]>);
+
 /// A string and its correspondings mappings.
 ///
 /// This is typically used to represent a name or sequence from a fastq record.
@@ -82,6 +85,14 @@ impl StrMappings {
         }
     }
 
+    /// Create the string mappings of a sequence, with quality scores if there are any.
+    pub fn from_record(seq: &[u8], qual: Option<&[u8]>, origin: Arc<Origin>, idx: usize) -> Self {
+        match qual {
+            Some(qual) => Self::new_with_qual(seq.to_owned(), qual.to_owned(), origin, idx),
+            None => Self::new(seq.to_owned(), origin, idx),
+        }
+    }
+
     pub fn data(&self, label: InlineString, attr: InlineString) -> Option<&Data> {
         self.mapping(label).and_then(|m| m.data(attr))
     }
@@ -322,6 +333,45 @@ impl StrMappings {
 
         Ok(())
     }
+
+    /// Create new string mappings that only contains the substring of the specified mapping.
+    ///
+    /// Mappings that are completely inside the extracted mapping are kept and shifted.
+    /// The attributes of the `*` mapping are kept.
+    pub fn extract(&self, label: InlineString, idx: usize) -> Result<Self, NameError> {
+        let extracted = self
+            .mapping(label)
+            .ok_or_else(|| NameError::NotInRead(Name::Label(label)))?;
+        let start = extracted.start;
+        let end = extracted.start + extracted.len;
+
+        let mappings = self
+            .mappings
+            .iter()
+            .filter_map(|m| {
+                if m.label == InlineString::star() {
+                    let mut m = m.clone();
+                    m.start = 0;
+                    m.len = end - start;
+                    Some(m)
+                } else if m.start >= start && m.start + m.len <= end {
+                    let mut m = m.clone();
+                    m.start -= start;
+                    Some(m)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            mappings,
+            string: self.string[start..end].to_owned(),
+            qual: self.qual.as_ref().map(|q| q[start..end].to_owned()),
+            origin: Arc::clone(&self.origin),
+            idx,
+        })
+    }
 }
 
 /// A labeled mapping that corresponds to an interval/region in a string.
@@ -338,6 +388,8 @@ pub struct Mapping {
 pub enum Data {
     Bool(bool),
     UInt(usize),
+    Int(isize),
+    Float(f64),
     Bytes(Vec<u8>),
 }
 
@@ -355,7 +407,7 @@ pub enum Intersection {
 impl Mapping {
     pub fn new_default(len: usize) -> Self {
         Self {
-            label: InlineString::new(b"*"),
+            label: InlineString::star(),
             start: 0,
             len,
             data: FxHashMap::default(),
@@ -445,67 +497,131 @@ impl Mapping {
 }
 
 impl Read {
+    /// Create a read from a fastq record.
+    ///
+    /// The sequence has no quality scores if `qual` is `None` (e.g., for fasta records).
     pub fn from_fastq1(
         name: &[u8],
         seq: &[u8],
-        qual: &[u8],
+        qual: Option<&[u8]>,
         origin: Arc<Origin>,
         idx: usize,
     ) -> Self {
         let name = StrMappings::new(name.to_owned(), Arc::clone(&origin), idx);
-        let seq = StrMappings::new_with_qual(seq.to_owned(), qual.to_owned(), origin, idx);
+        let seq = StrMappings::from_record(seq, qual, origin, idx);
 
         Self {
-            str_mappings: vec![(StrType::Name1, name), (StrType::Seq1, seq)],
+            str_mappings: vec![(StrType::Name(1), name), (StrType::Seq(1), seq)],
         }
     }
 
+    /// Create a read from two fastq records.
+    ///
+    /// Each sequence has no quality scores if its `qual` is `None` (e.g., for fasta records).
     pub fn from_fastq2(
         name1: &[u8],
         seq1: &[u8],
-        qual1: &[u8],
+        qual1: Option<&[u8]>,
         origin1: Arc<Origin>,
         idx1: usize,
         name2: &[u8],
         seq2: &[u8],
-        qual2: &[u8],
+        qual2: Option<&[u8]>,
         origin2: Arc<Origin>,
         idx2: usize,
     ) -> Self {
         let name1 = StrMappings::new(name1.to_owned(), Arc::clone(&origin1), idx1);
-        let seq1 = StrMappings::new_with_qual(seq1.to_owned(), qual1.to_owned(), origin1, idx1);
+        let seq1 = StrMappings::from_record(seq1, qual1, origin1, idx1);
         let name2 = StrMappings::new(name2.to_owned(), Arc::clone(&origin2), idx2);
-        let seq2 = StrMappings::new_with_qual(seq2.to_owned(), qual2.to_owned(), origin2, idx2);
+        let seq2 = StrMappings::from_record(seq2, qual2, origin2, idx2);
 
         Self {
             str_mappings: vec![
-                (StrType::Name1, name1),
-                (StrType::Seq1, seq1),
-                (StrType::Name2, name2),
-                (StrType::Seq2, seq2),
+                (StrType::Name(1), name1),
+                (StrType::Seq(1), seq1),
+                (StrType::Name(2), name2),
+                (StrType::Seq(2), seq2),
             ],
         }
     }
 
-    pub fn to_fastq1(&self) -> (&[u8], &[u8], &[u8]) {
-        let name = self.str_mappings(StrType::Name1).unwrap();
-        let seq = self.str_mappings(StrType::Seq1).unwrap();
-        (name.string(), seq.string(), seq.qual().unwrap())
+    /// Create a read from a fasta record, without quality scores.
+    pub fn from_fasta1(name: &[u8], seq: &[u8], origin: Arc<Origin>, idx: usize) -> Self {
+        Self::from_fastq1(name, seq, None, origin, idx)
+    }
+
+    /// Create a read from two fasta records, without quality scores.
+    pub fn from_fasta2(
+        name1: &[u8],
+        seq1: &[u8],
+        origin1: Arc<Origin>,
+        idx1: usize,
+        name2: &[u8],
+        seq2: &[u8],
+        origin2: Arc<Origin>,
+        idx2: usize,
+    ) -> Self {
+        Self::from_fastq2(
+            name1, seq1, None, origin1, idx1, name2, seq2, None, origin2, idx2,
+        )
+    }
+
+    pub fn to_fastq1(&self) -> FastqRecord<'_> {
+        let name = self.str_mappings(StrType::Name(1)).unwrap();
+        let seq = self.str_mappings(StrType::Seq(1)).unwrap();
+        (name.string(), seq.string(), seq.qual())
     }
 
-    pub fn to_fastq2(&self) -> Result<((&[u8], &[u8], &[u8]), (&[u8], &[u8], &[u8])), NameError> {
-        let name1 = self.str_mappings(StrType::Name1).unwrap();
-        let seq1 = self.str_mappings(StrType::Seq1).unwrap();
+    pub fn to_fastq2(&self) -> Result<(FastqRecord<'_>, FastqRecord<'_>), NameError> {
+        let name1 = self.str_mappings(StrType::Name(1)).unwrap();
+        let seq1 = self.str_mappings(StrType::Seq(1)).unwrap();
         let name2 = self
-            .str_mappings(StrType::Name2)
-            .ok_or_else(|| NameError::NotInRead(Name::StrType(StrType::Name2)))?;
-        let seq2 = self.str_mappings(StrType::Seq2).unwrap();
+            .str_mappings(StrType::Name(2))
+            .ok_or_else(|| NameError::NotInRead(Name::StrType(StrType::Name(2))))?;
+        let seq2 = self.str_mappings(StrType::Seq(2)).unwrap();
         Ok((
-            (name1.string(), seq1.string(), seq1.qual().unwrap()),
-            (name2.string(), seq2.string(), seq2.qual().unwrap()),
+            (name1.string(), seq1.string(), seq1.qual()),
+            (name2.string(), seq2.string(), seq2.qual()),
         ))
     }
 
+    pub fn to_fastq(
+        &self,
+        name_type: StrType,
+        seq_type: StrType,
+    ) -> Result<FastqRecord<'_>, NameError> {
+        let name = self
+            .str_mappings(name_type)
+            .ok_or_else(|| NameError::NotInRead(Name::StrType(name_type)))?;
+        let seq = self
+            .str_mappings(seq_type)
+            .ok_or_else(|| NameError::NotInRead(Name::StrType(seq_type)))?;
+        Ok((name.string(), seq.string(), seq.qual()))
+    }
+
+    pub fn to_fasta(
+        &self,
+        name_type: StrType,
+        seq_type: StrType,
+    ) -> Result<(&[u8], &[u8]), NameError> {
+        let (name, seq, _) = self.to_fastq(name_type, seq_type)?;
+        Ok((name, seq))
+    }
+
+    pub fn to_sam(
+        &self,
+        name_type: StrType,
+        seq_type: StrType,
+    ) -> Result<(&[u8], &[u8], Option<&[u8]>), NameError> {
+        let name = self
+            .str_mappings(name_type)
+            .ok_or_else(|| NameError::NotInRead(Name::StrType(name_type)))?;
+        let seq = self
+            .str_mappings(seq_type)
+            .ok_or_else(|| NameError::NotInRead(Name::StrType(seq_type)))?;
+        Ok((name.string(), seq.string(), seq.qual()))
+    }
+
     pub fn str_mappings(&self, str_type: StrType) -> Option<&StrMappings> {
         self.str_mappings
             .iter()
@@ -518,6 +634,18 @@ impl Read {
             .find_map(|(t, m)| if *t == str_type { Some(m) } else { None })
     }
 
+    pub fn add_str_mappings(
+        &mut self,
+        str_type: StrType,
+        str_mappings: StrMappings,
+    ) -> Result<(), NameError> {
+        if self.str_mappings(str_type).is_some() {
+            Err(NameError::Duplicate(Name::StrType(str_type)))?
+        }
+        self.str_mappings.push((str_type, str_mappings));
+        Ok(())
+    }
+
     pub fn mapping(&self, str_type: StrType, label: InlineString) -> Result<&Mapping, NameError> {
         self.str_mappings(str_type)
             .ok_or_else(|| NameError::NotInRead(Name::StrType(str_type)))?
@@ -643,6 +771,43 @@ impl Read {
             .trim(label)
     }
 
+    /// Create a new read where the specified string only contains the substring of a mapping.
+    ///
+    /// All other strings are cloned, and all strings in the new read will have the record index `idx`.
+    pub fn split(
+        &self,
+        str_type: StrType,
+        label: InlineString,
+        idx: usize,
+    ) -> Result<Self, NameError> {
+        let mut extracted = Some(
+            self.str_mappings(str_type)
+                .ok_or_else(|| NameError::NotInRead(Name::StrType(str_type)))?
+                .extract(label, idx)?,
+        );
+
+        let mut res = Self {
+            str_mappings: self
+                .str_mappings
+                .iter()
+                .map(|(t, m)| {
+                    if *t == str_type {
+                        (*t, extracted.take().unwrap())
+                    } else {
+                        (*t, m.clone())
+                    }
+                })
+                .collect(),
+        };
+        res.set_idx(idx);
+        Ok(res)
+    }
+
+    /// Set the record index of all strings in the read.
+    pub fn set_idx(&mut self, idx: usize) {
+        self.str_mappings.iter_mut().for_each(|(_, m)| m.idx = idx);
+    }
+
     pub fn first_idx(&self) -> usize {
         self.str_mappings.iter().map(|(_, s)| s.idx).min().unwrap()
     }
@@ -654,6 +819,8 @@ impl Data {
         match self {
             Bool(x) => *x,
             UInt(x) => *x > 0,
+            Int(x) => *x != 0,
+            Float(x) => *x != 0.0,
             Bytes(x) => !x.is_empty(),
         }
     }
@@ -663,7 +830,23 @@ impl Data {
         match self {
             Bool(x) => Ok(if *x { 1 } else { 0 }),
             UInt(x) => Ok(*x),
-            Bytes(_) => Err(NameError::Type("bool or uint", self.clone())),
+            Int(x) if *x >= 0 => Ok(*x as usize),
+            Float(x) if *x >= 0.0 && x.fract() == 0.0 => Ok(*x as usize),
+            _ => Err(NameError::Type(
+                "bool or non-negative integer",
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<f64, NameError> {
+        use Data::*;
+        match self {
+            Bool(x) => Ok(if *x { 1.0 } else { 0.0 }),
+            UInt(x) => Ok(*x as f64),
+            Int(x) => Ok(*x as f64),
+            Float(x) => Ok(*x),
+            Bytes(_) => Err(NameError::Type("bool or number", self.clone())),
         }
     }
 
@@ -672,6 +855,8 @@ impl Data {
         match self {
             Bool(_) => Err(NameError::Type("bytes", self.clone())),
             UInt(_) => Err(NameError::Type("bytes", self.clone())),
+            Int(_) => Err(NameError::Type("bytes", self.clone())),
+            Float(_) => Err(NameError::Type("bytes", self.clone())),
             Bytes(x) => Ok(x.len()),
         }
     }
@@ -768,6 +953,11 @@ impl fmt::Display for Data {
         match self {
             Bool(x) => write!(f, "{}", x),
             UInt(x) => write!(f, "{}", x),
+            Int(x) => write!(f, "{}", x),
+            Float(x) => match f.precision() {
+                Some(p) => write!(f, "{:.*}", p, x),
+                None => write!(f, "{}", x),
+            },
             Bytes(x) => write!(f, "{}", std::str::from_utf8(x).unwrap()),
         }
     }
@@ -779,6 +969,8 @@ impl fmt::Debug for Data {
         match self {
             Bool(x) => write!(f, "bool {}", x),
             UInt(x) => write!(f, "uint {}", x),
+            Int(x) => write!(f, "int {}", x),
+            Float(x) => write!(f, "float {}", x),
             Bytes(x) => write!(f, "bytes \"{}\"", std::str::from_utf8(x).unwrap()),
         }
     }
@@ -787,18 +979,32 @@ impl fmt::Debug for Data {
 impl StrType {
     pub fn new(str_type: &[u8]) -> Result<Self, errors::Error> {
         use StrType::*;
-        match str_type {
-            b"name1" => Ok(Name1),
-            b"seq1" => Ok(Seq1),
-            b"name2" => Ok(Name2),
-            b"seq2" => Ok(Seq2),
-            b"index1" => Ok(Index1),
-            b"index2" => Ok(Index2),
-            _ => Err(errors::Error::Parse {
-                string: errors::utf8(str_type),
-                context: errors::utf8(str_type),
-                reason: "not a known valid string type. Expected \"name1\", \"seq1\", etc.",
-            }),
+        let err = || errors::Error::Parse {
+            string: errors::utf8(str_type),
+            context: errors::utf8(str_type),
+            reason: "not a known valid string type. Expected \"name1\", \"seq1\", etc.",
+        };
+
+        let split = str_type
+            .iter()
+            .position(|c| c.is_ascii_digit())
+            .ok_or_else(err)?;
+        let (prefix, num) = str_type.split_at(split);
+
+        // no leading zeros, so each string type has exactly one representation
+        if num[0] == b'0' || !num.iter().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        let num = std::str::from_utf8(num)
+            .unwrap()
+            .parse::<usize>()
+            .map_err(|_| err())?;
+
+        match prefix {
+            b"name" => Ok(Name(num)),
+            b"seq" => Ok(Seq(num)),
+            b"index" => Ok(Index(num)),
+            _ => Err(err()),
         }
     }
 }
@@ -807,12 +1013,9 @@ impl fmt::Display for StrType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use StrType::*;
         match self {
-            Name1 => write!(f, "name1"),
-            Seq1 => write!(f, "seq1"),
-            Name2 => write!(f, "name2"),
-            Seq2 => write!(f, "seq2"),
-            Index1 => write!(f, "index1"),
-            Index2 => write!(f, "index2"),
+            Name(num) => write!(f, "name{num}"),
+            Seq(num) => write!(f, "seq{num}"),
+            Index(num) => write!(f, "index{num}"),
         }
     }
 }
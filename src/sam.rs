@@ -0,0 +1,1006 @@
+use flate2::{read::MultiGzDecoder, write::DeflateEncoder, Compression, Crc};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read as _, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::errors::*;
+use crate::expr::*;
+use crate::fastq::Origin;
+use crate::inline_string::*;
+use crate::iter::*;
+use crate::read::*;
+use crate::seq_utils::*;
+
+const FLAG_PAIRED: u16 = 0x1;
+const FLAG_REVERSE: u16 = 0x10;
+const FLAG_READ1: u16 = 0x40;
+const FLAG_READ2: u16 = 0x80;
+const FLAG_SECONDARY: u16 = 0x100;
+const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+const BAM_SEQ: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+const MAX_QNAME_LEN: usize = 254;
+const MAX_BAM_QUAL: u8 = 93;
+
+pub struct SamReads<'a> {
+    reader: Mutex<Box<dyn SamRecordReader + 'a>>,
+    origin: Arc<Origin>,
+    idx: AtomicUsize,
+    chunk_size: usize,
+}
+
+impl<'a> Reads for SamReads<'a> {
+    fn next_chunk(&self) -> Result<Vec<Read>> {
+        let mut res = Vec::with_capacity(self.chunk_size);
+
+        let mut reader = self.reader.lock().unwrap();
+
+        for _ in 0..self.chunk_size {
+            let Some(record1) = self.next_record(&mut **reader)? else {
+                break;
+            };
+
+            if record1.flag & FLAG_PAIRED == 0 {
+                let idx = self.idx.fetch_add(1, Ordering::Relaxed);
+                res.push(record1.into_read(None, Arc::clone(&self.origin), idx));
+                continue;
+            }
+
+            // mates are expected to be adjacent, with read 1 first
+            let Some(record2) = self.next_record(&mut **reader)? else {
+                Err(Error::UnpairedRead(format!(
+                    "\"{}\" for record \"{}\"",
+                    &*self.origin,
+                    utf8(&record1.name)
+                )))?
+            };
+
+            if record1.flag & FLAG_READ1 == 0
+                || record2.flag & FLAG_PAIRED == 0
+                || record2.flag & FLAG_READ2 == 0
+                || record1.name != record2.name
+            {
+                Err(Error::UnpairedRead(format!(
+                    "\"{}\" for records \"{}\" and \"{}\"",
+                    &*self.origin,
+                    utf8(&record1.name),
+                    utf8(&record2.name)
+                )))?
+            }
+
+            let idx = self.idx.fetch_add(2, Ordering::Relaxed);
+            res.push(record1.into_read(Some(record2), Arc::clone(&self.origin), idx));
+        }
+
+        Ok(res)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SamReads<'a> {
+    /// Get the next primary record, skipping secondary and supplementary alignments.
+    fn next_record(&self, reader: &mut dyn SamRecordReader) -> Result<Option<SamRecord>> {
+        loop {
+            let record = reader.next_record().map_err(|e| Error::ParseRecord {
+                origin: (*self.origin).clone(),
+                idx: self.idx.load(Ordering::Relaxed),
+                source: Box::new(e),
+            })?;
+
+            match record {
+                Some(r) if r.flag & (FLAG_SECONDARY | FLAG_SUPPLEMENTARY) != 0 => continue,
+                r => return Ok(r),
+            }
+        }
+    }
+}
+
+/// Create a read iterator over unaligned SAM records from a file.
+///
+/// The file can be gzip compressed if it ends with `.gz`.
+///
+/// Paired records (with the `0x1` flag) must be adjacent and they are stored in the same `Read`.
+/// Each auxiliary tag is stored as an attribute of the `*` mapping of the corresponding sequence.
+/// For example, the `CB` tag of read 1 can be accessed with `seq1.*.CB`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_sam(file: impl AsRef<str>, chunk_size: usize) -> Result<SamReads<'static>> {
+    let f = File::open(file.as_ref()).map_err(|e| Error::FileIo {
+        file: file.as_ref().to_owned(),
+        source: Box::new(e),
+    })?;
+    let reader: Box<dyn SamRecordReader> = if file.as_ref().ends_with(".gz") {
+        Box::new(SamTextReader::new(BufReader::new(MultiGzDecoder::new(
+            BufReader::new(f),
+        ))))
+    } else {
+        Box::new(SamTextReader::new(BufReader::new(f)))
+    };
+
+    Ok(SamReads::<'static> {
+        reader: Mutex::new(reader),
+        origin: Arc::new(Origin::File(file.as_ref().to_owned())),
+        idx: AtomicUsize::new(0),
+        chunk_size,
+    })
+}
+
+/// Create a read iterator over unaligned BAM (uBAM) records from a file.
+///
+/// Paired records (with the `0x1` flag) must be adjacent and they are stored in the same `Read`.
+/// Each auxiliary tag is stored as an attribute of the `*` mapping of the corresponding sequence.
+/// For example, the `CB` tag of read 1 can be accessed with `seq1.*.CB`.
+///
+/// Larger `chunk_size` uses more memory, but reduces the overhead of allocations, multithreading,
+/// etc.
+#[must_use]
+pub fn iter_bam(file: impl AsRef<str>, chunk_size: usize) -> Result<SamReads<'static>> {
+    let map_err = |e| Error::FileIo {
+        file: file.as_ref().to_owned(),
+        source: Box::new(e),
+    };
+    let f = File::open(file.as_ref()).map_err(map_err)?;
+    let reader =
+        BamReader::new(BufReader::new(MultiGzDecoder::new(BufReader::new(f)))).map_err(map_err)?;
+
+    Ok(SamReads::<'static> {
+        reader: Mutex::new(Box::new(reader)),
+        origin: Arc::new(Origin::File(file.as_ref().to_owned())),
+        idx: AtomicUsize::new(0),
+        chunk_size,
+    })
+}
+
+/// Create a read iterator over unaligned SAM records from a byte slice.
+#[must_use]
+pub fn iter_sam_bytes<'a>(bytes: &'a [u8]) -> Result<SamReads<'a>> {
+    Ok(SamReads::<'a> {
+        reader: Mutex::new(Box::new(SamTextReader::new(bytes))),
+        origin: Arc::new(Origin::Bytes),
+        idx: AtomicUsize::new(0),
+        chunk_size: 256,
+    })
+}
+
+/// Create a read iterator over unaligned BAM records from a byte slice.
+#[must_use]
+pub fn iter_bam_bytes<'a>(bytes: &'a [u8]) -> Result<SamReads<'a>> {
+    let reader = BamReader::new(BufReader::new(MultiGzDecoder::new(bytes)))
+        .map_err(|e| Error::BytesIo(Box::new(e)))?;
+
+    Ok(SamReads::<'a> {
+        reader: Mutex::new(Box::new(reader)),
+        origin: Arc::new(Origin::Bytes),
+        idx: AtomicUsize::new(0),
+        chunk_size: 256,
+    })
+}
+
+struct SamRecord {
+    name: Vec<u8>,
+    flag: u16,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+    tags: Vec<(InlineString, Data)>,
+}
+
+impl SamRecord {
+    fn into_read(self, mate: Option<SamRecord>, origin: Arc<Origin>, idx: usize) -> Read {
+        let record1 = self.orient();
+
+        let Some(record2) = mate.map(|m| m.orient()) else {
+            let mut read = Read::from_fastq1(
+                &record1.name,
+                &record1.seq,
+                record1.qual.as_deref(),
+                origin,
+                idx,
+            );
+            record1.set_tags(&mut read, StrType::Seq(1));
+            return read;
+        };
+
+        // each mate keeps its own quality scores, even if the other mate does not have any
+        let mut read = Read::from_fastq2(
+            &record1.name,
+            &record1.seq,
+            record1.qual.as_deref(),
+            Arc::clone(&origin),
+            idx,
+            &record2.name,
+            &record2.seq,
+            record2.qual.as_deref(),
+            origin,
+            idx + 1,
+        );
+        record1.set_tags(&mut read, StrType::Seq(1));
+        record2.set_tags(&mut read, StrType::Seq(2));
+        read
+    }
+
+    /// Restore the original orientation of reverse strand records.
+    fn orient(mut self) -> Self {
+        if self.flag & FLAG_REVERSE != 0 {
+            self.seq.reverse();
+            self.seq.iter_mut().for_each(|c| *c = complement(*c));
+
+            if let Some(qual) = &mut self.qual {
+                qual.reverse();
+            }
+        }
+
+        self
+    }
+
+    fn set_tags(&self, read: &mut Read, str_type: StrType) {
+        let star = InlineString::star();
+
+        for (tag, data) in &self.tags {
+            *read.data_mut(str_type, star, *tag).unwrap() = data.clone();
+        }
+    }
+}
+
+fn int_data(x: i64) -> Data {
+    if x >= 0 {
+        Data::UInt(x as usize)
+    } else {
+        Data::Int(x as isize)
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+trait SamRecordReader: Send {
+    fn next_record(&mut self) -> io::Result<Option<SamRecord>>;
+}
+
+struct SamTextReader<R: BufRead + Send> {
+    reader: R,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead + Send> SamTextReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead + Send> SamRecordReader for SamTextReader<R> {
+    fn next_record(&mut self) -> io::Result<Option<SamRecord>> {
+        loop {
+            self.line.clear();
+
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+
+            while let Some(b'\n' | b'\r') = self.line.last() {
+                self.line.pop();
+            }
+
+            if !self.line.is_empty() && self.line[0] != b'@' {
+                break;
+            }
+        }
+
+        let fields = self.line.split(|&c| c == b'\t').collect::<Vec<_>>();
+
+        if fields.len() < 11 {
+            return Err(invalid_data(format!(
+                "expected at least 11 fields in SAM record, but found {}",
+                fields.len()
+            )));
+        }
+
+        let flag = std::str::from_utf8(fields[1])
+            .ok()
+            .and_then(|f| f.parse::<u16>().ok())
+            .ok_or_else(|| invalid_data(format!("invalid SAM flag \"{}\"", utf8(fields[1]))))?;
+
+        let seq = if fields[9] == b"*" {
+            Vec::new()
+        } else {
+            fields[9].to_owned()
+        };
+        let qual = if fields[10] == b"*" {
+            None
+        } else {
+            Some(fields[10].to_owned())
+        };
+
+        let tags = fields[11..]
+            .iter()
+            .map(|&t| parse_sam_tag(t))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Some(SamRecord {
+            name: fields[0].to_owned(),
+            flag,
+            seq,
+            qual,
+            tags,
+        }))
+    }
+}
+
+fn parse_sam_tag(t: &[u8]) -> io::Result<(InlineString, Data)> {
+    if t.len() < 5 || t[2] != b':' || t[4] != b':' {
+        return Err(invalid_data(format!("invalid SAM tag \"{}\"", utf8(t))));
+    }
+
+    let tag = InlineString::try_new(&t[..2], t).map_err(|e| invalid_data(e.to_string()))?;
+    let value = &t[5..];
+
+    let data = match t[3] {
+        b'i' => int_data(
+            std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .ok_or_else(|| {
+                    invalid_data(format!("invalid integer in SAM tag \"{}\"", utf8(t)))
+                })?,
+        ),
+        b'f' => Data::Float(
+            std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| invalid_data(format!("invalid float in SAM tag \"{}\"", utf8(t))))?,
+        ),
+        b'A' | b'Z' | b'H' | b'B' => Data::Bytes(value.to_owned()),
+        _ => {
+            return Err(invalid_data(format!(
+                "invalid SAM tag type in \"{}\"",
+                utf8(t)
+            )))
+        }
+    };
+
+    Ok((tag, data))
+}
+
+struct BamReader<R: BufRead + Send> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead + Send> BamReader<R> {
+    fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != b"BAM\x01" {
+            return Err(invalid_data("invalid BAM magic bytes"));
+        }
+
+        // skip the header text and the reference sequences
+        let l_text = read_u32(&mut reader)? as usize;
+        io::copy(&mut (&mut reader).take(l_text as u64), &mut io::sink())?;
+        let n_ref = read_u32(&mut reader)?;
+
+        for _ in 0..n_ref {
+            let l_name = read_u32(&mut reader)? as u64;
+            io::copy(&mut (&mut reader).take(l_name + 4), &mut io::sink())?;
+        }
+
+        Ok(Self {
+            reader,
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl<R: BufRead + Send> SamRecordReader for BamReader<R> {
+    fn next_record(&mut self) -> io::Result<Option<SamRecord>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let block_size = read_u32(&mut self.reader)? as usize;
+        self.buf.resize(block_size, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        let b = &self.buf;
+
+        if b.len() < 32 {
+            return Err(invalid_data("BAM record is too short"));
+        }
+
+        let l_read_name = b[8] as usize;
+        let n_cigar_op = u16::from_le_bytes([b[12], b[13]]) as usize;
+        let flag = u16::from_le_bytes([b[14], b[15]]);
+        let l_seq = u32::from_le_bytes([b[16], b[17], b[18], b[19]]) as usize;
+
+        let name_start = 32;
+        let seq_start = name_start + l_read_name + n_cigar_op * 4;
+        let qual_start = seq_start + l_seq.div_ceil(2);
+        let tags_start = qual_start + l_seq;
+
+        if b.len() < tags_start || l_read_name == 0 {
+            return Err(invalid_data("BAM record is too short"));
+        }
+
+        // read name is null terminated
+        let name = b[name_start..name_start + l_read_name - 1].to_owned();
+
+        let seq = (0..l_seq)
+            .map(|i| {
+                let c = b[seq_start + i / 2];
+                let c = if i % 2 == 0 { c >> 4 } else { c & 0b1111 };
+                BAM_SEQ[c as usize]
+            })
+            .collect::<Vec<_>>();
+
+        let qual = &b[qual_start..tags_start];
+        let qual = if l_seq > 0 && qual[0] == 0xFF {
+            None
+        } else {
+            // quality scores must be printable characters after adding the offset
+            if qual.iter().any(|&q| q > MAX_BAM_QUAL) {
+                return Err(invalid_data(format!(
+                    "BAM quality score is greater than {MAX_BAM_QUAL}"
+                )));
+            }
+            Some(qual.iter().map(|&q| q + 33).collect::<Vec<_>>())
+        };
+
+        let mut tags = Vec::new();
+        let mut t = &b[tags_start..];
+
+        while !t.is_empty() {
+            tags.push(parse_bam_tag(&mut t)?);
+        }
+
+        Ok(Some(SamRecord {
+            name,
+            flag,
+            seq,
+            qual,
+            tags,
+        }))
+    }
+}
+
+fn parse_bam_tag(t: &mut &[u8]) -> io::Result<(InlineString, Data)> {
+    let header = take_bytes(t, 3)?;
+    let tag =
+        InlineString::try_new(&header[..2], header).map_err(|e| invalid_data(e.to_string()))?;
+
+    let data = match header[2] {
+        b'A' => Data::Bytes(take_bytes(t, 1)?.to_owned()),
+        b'Z' | b'H' => {
+            let end = t
+                .iter()
+                .position(|&c| c == 0)
+                .ok_or_else(|| invalid_data("unterminated string in BAM tag"))?;
+            let s = take_bytes(t, end + 1)?;
+            Data::Bytes(s[..end].to_owned())
+        }
+        b'f' => {
+            let v = take_bytes(t, 4)?;
+            let x = f32::from_le_bytes([v[0], v[1], v[2], v[3]]);
+            Data::Float(x as f64)
+        }
+        b'B' => {
+            let sub_type = take_bytes(t, 1)?[0];
+            let v = take_bytes(t, 4)?;
+            let count = u32::from_le_bytes([v[0], v[1], v[2], v[3]]);
+            // store arrays in the same text format as SAM
+            let mut s = vec![sub_type];
+
+            for _ in 0..count {
+                s.extend_from_slice(b",");
+
+                if sub_type == b'f' {
+                    let v = take_bytes(t, 4)?;
+                    let x = f32::from_le_bytes([v[0], v[1], v[2], v[3]]);
+                    s.extend(x.to_string().into_bytes());
+                } else {
+                    s.extend(take_bam_int(t, sub_type)?.to_string().into_bytes());
+                }
+            }
+
+            Data::Bytes(s)
+        }
+        ty => int_data(take_bam_int(t, ty)?),
+    };
+
+    Ok((tag, data))
+}
+
+fn take_bam_int(t: &mut &[u8], ty: u8) -> io::Result<i64> {
+    let x = match ty {
+        b'c' => take_bytes(t, 1)?[0] as i8 as i64,
+        b'C' => take_bytes(t, 1)?[0] as i64,
+        b's' => {
+            let v = take_bytes(t, 2)?;
+            i16::from_le_bytes([v[0], v[1]]) as i64
+        }
+        b'S' => {
+            let v = take_bytes(t, 2)?;
+            u16::from_le_bytes([v[0], v[1]]) as i64
+        }
+        b'i' => {
+            let v = take_bytes(t, 4)?;
+            i32::from_le_bytes([v[0], v[1], v[2], v[3]]) as i64
+        }
+        b'I' => {
+            let v = take_bytes(t, 4)?;
+            u32::from_le_bytes([v[0], v[1], v[2], v[3]]) as i64
+        }
+        _ => {
+            return Err(invalid_data(format!(
+                "invalid BAM tag type '{}'",
+                ty as char
+            )))
+        }
+    };
+
+    Ok(x)
+}
+
+fn take_bytes<'a>(t: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if t.len() < n {
+        return Err(invalid_data("BAM tag is too short"));
+    }
+
+    let (res, rest) = t.split_at(n);
+    *t = rest;
+    Ok(res)
+}
+
+fn read_u32(reader: &mut impl BufRead) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    reader.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+/// A SAM tag that is set to the value of a label or attribute when writing SAM/BAM records.
+#[derive(Debug, Clone)]
+pub struct SamTag {
+    tag: [u8; 2],
+    label_or_attr: LabelOrAttr,
+    qual: bool,
+}
+
+impl SamTag {
+    /// Create a SAM tag that is set to a mapping's substring or an attribute.
+    ///
+    /// Attributes keep their types, so uint attributes are written as integers.
+    pub fn new(tag: impl AsRef<str>, label_or_attr: impl Into<LabelOrAttr>) -> Self {
+        Self {
+            tag: parse_tag_name(tag.as_ref()),
+            label_or_attr: label_or_attr.into(),
+            qual: false,
+        }
+    }
+
+    /// Create a SAM tag that is set to the quality scores of a mapping.
+    pub fn qual(tag: impl AsRef<str>, label: Label) -> Self {
+        Self {
+            tag: parse_tag_name(tag.as_ref()),
+            label_or_attr: LabelOrAttr::Label(label),
+            qual: true,
+        }
+    }
+
+    pub fn tag(&self) -> [u8; 2] {
+        self.tag
+    }
+
+    /// Get the value of the tag for a read, or `None` if the label or attribute is not in the read.
+    pub fn data(&self, read: &Read) -> std::result::Result<Option<Data>, NameError> {
+        let res = match &self.label_or_attr {
+            LabelOrAttr::Label(l) if self.qual => read
+                .substring_qual(l.str_type, l.label)
+                .map(|q| q.map(|q| Data::Bytes(q.to_owned()))),
+            LabelOrAttr::Label(l) => read
+                .substring(l.str_type, l.label)
+                .map(|s| Some(Data::Bytes(s.to_owned()))),
+            LabelOrAttr::Attr(a) => read
+                .data(a.str_type, a.label, a.attr)
+                .map(|d| Some(d.clone())),
+        };
+
+        match res {
+            Err(NameError::NotInRead(_)) => Ok(None),
+            r => r,
+        }
+    }
+}
+
+fn parse_tag_name(tag: &str) -> [u8; 2] {
+    let b = tag.as_bytes();
+    assert!(
+        b.len() == 2 && b[0].is_ascii_alphabetic() && b[1].is_ascii_alphanumeric(),
+        "Invalid SAM tag \"{}\". Tags must be two characters, like \"CB\"",
+        tag
+    );
+    [b[0], b[1]]
+}
+
+/// Unaligned flags for an unpaired record, read 1, or read 2.
+pub fn sam_flag(paired: bool, mate: usize) -> u16 {
+    let unmapped = 0x4;
+
+    if paired {
+        let mate_flag = if mate == 1 { FLAG_READ1 } else { FLAG_READ2 };
+        FLAG_PAIRED | unmapped | 0x8 | mate_flag
+    } else {
+        unmapped
+    }
+}
+
+/// Convert a read name to a SAM query name.
+///
+/// Everything after the first whitespace and the `/1` or `/2` suffix are removed.
+pub fn sam_qname(name: &[u8]) -> &[u8] {
+    let end = name
+        .iter()
+        .position(|c| c.is_ascii_whitespace())
+        .unwrap_or(name.len());
+    let name = &name[..end];
+
+    if name.ends_with(b"/1") || name.ends_with(b"/2") {
+        &name[..name.len() - 2]
+    } else {
+        name
+    }
+}
+
+pub fn write_sam_header(writer: &mut (dyn Write + std::marker::Send)) {
+    writer.write_all(b"@HD\tVN:1.6\tSO:unsorted\n").unwrap();
+}
+
+pub fn write_sam_record(
+    writer: &mut (dyn Write + std::marker::Send),
+    qname: &[u8],
+    flag: u16,
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    tags: &[([u8; 2], Data)],
+) -> io::Result<()> {
+    writer.write_all(if qname.is_empty() { b"*" } else { qname })?;
+    write!(writer, "\t{}\t*\t0\t0\t*\t*\t0\t0\t", flag)?;
+    writer.write_all(if seq.is_empty() { b"*" } else { seq })?;
+    writer.write_all(b"\t")?;
+    match qual {
+        Some(q) if !q.is_empty() => writer.write_all(q)?,
+        _ => writer.write_all(b"*")?,
+    }
+
+    for (tag, data) in tags {
+        writer.write_all(b"\t")?;
+        writer.write_all(tag)?;
+
+        match data {
+            Data::Bool(x) => write!(writer, ":i:{}", *x as usize)?,
+            Data::UInt(x) => write_sam_int(writer, *x as i128)?,
+            Data::Int(x) => write_sam_int(writer, *x as i128)?,
+            Data::Float(x) => write!(writer, ":f:{}", x)?,
+            Data::Bytes(x) => {
+                writer.write_all(b":Z:")?;
+                writer.write_all(x)?;
+            }
+        }
+    }
+
+    writer.write_all(b"\n")
+}
+
+// integer tags must fit in either an i32 or a u32, so larger integers are written as strings
+fn write_sam_int(writer: &mut (dyn Write + std::marker::Send), x: i128) -> io::Result<()> {
+    if i32::try_from(x).is_ok() || u32::try_from(x).is_ok() {
+        write!(writer, ":i:{}", x)
+    } else {
+        write!(writer, ":Z:{}", x)
+    }
+}
+
+pub fn write_bam_header(writer: &mut (dyn Write + std::marker::Send)) {
+    let text = b"@HD\tVN:1.6\tSO:unsorted\n";
+    writer.write_all(b"BAM\x01").unwrap();
+    writer
+        .write_all(&(text.len() as u32).to_le_bytes())
+        .unwrap();
+    writer.write_all(text).unwrap();
+    // no reference sequences
+    writer.write_all(&0u32.to_le_bytes()).unwrap();
+}
+
+pub fn write_bam_record(
+    writer: &mut (dyn Write + std::marker::Send),
+    qname: &[u8],
+    flag: u16,
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    tags: &[([u8; 2], Data)],
+) -> io::Result<()> {
+    // the length of the query name, including the NUL terminator, is stored in a single byte
+    if qname.len() > MAX_QNAME_LEN {
+        return Err(invalid_data(format!(
+            "query name \"{}\" is longer than {MAX_QNAME_LEN} bytes",
+            String::from_utf8_lossy(qname)
+        )));
+    }
+
+    let mut b = Vec::new();
+
+    // unaligned, so the reference and position are unset
+    b.extend((-1i32).to_le_bytes());
+    b.extend((-1i32).to_le_bytes());
+    b.push(qname.len() as u8 + 1);
+    b.push(255);
+    b.extend(4680u16.to_le_bytes());
+    b.extend(0u16.to_le_bytes());
+    b.extend(flag.to_le_bytes());
+    b.extend((seq.len() as u32).to_le_bytes());
+    b.extend((-1i32).to_le_bytes());
+    b.extend((-1i32).to_le_bytes());
+    b.extend(0i32.to_le_bytes());
+    b.extend(qname);
+    b.push(0);
+
+    for pair in seq.chunks(2) {
+        let hi = bam_seq_code(pair[0]);
+        let lo = pair.get(1).map(|&c| bam_seq_code(c)).unwrap_or(0);
+        b.push((hi << 4) | lo);
+    }
+
+    match qual {
+        Some(q) => b.extend(q.iter().map(|&q| q.saturating_sub(33))),
+        None => b.extend(std::iter::repeat_n(0xFF, seq.len())),
+    }
+
+    for (tag, data) in tags {
+        b.extend(tag);
+
+        match data {
+            Data::Bool(x) => {
+                b.push(b'C');
+                b.push(*x as u8);
+            }
+            Data::UInt(x) => push_bam_int(&mut b, *x as i128),
+            Data::Int(x) => push_bam_int(&mut b, *x as i128),
+            Data::Float(x) => {
+                b.push(b'f');
+                b.extend((*x as f32).to_le_bytes());
+            }
+            Data::Bytes(x) => {
+                b.push(b'Z');
+                b.extend(x);
+                b.push(0);
+            }
+        }
+    }
+
+    writer.write_all(&(b.len() as u32).to_le_bytes())?;
+    writer.write_all(&b)
+}
+
+// integer tags must fit in either an i32 or a u32, so larger integers are written as strings
+fn push_bam_int(b: &mut Vec<u8>, x: i128) {
+    if let Ok(x) = i32::try_from(x) {
+        b.push(b'i');
+        b.extend(x.to_le_bytes());
+    } else if let Ok(x) = u32::try_from(x) {
+        b.push(b'I');
+        b.extend(x.to_le_bytes());
+    } else {
+        b.push(b'Z');
+        b.extend(x.to_string().into_bytes());
+        b.push(0);
+    }
+}
+
+fn bam_seq_code(c: u8) -> u8 {
+    BAM_SEQ
+        .iter()
+        .position(|&s| s == c.to_ascii_uppercase())
+        .unwrap_or(15) as u8
+}
+
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Writer for the blocked gzip format used by BAM files.
+///
+/// The end-of-file marker block is written when the writer is dropped.
+pub struct BgzfWriter<W: Write> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+
+    fn write_block(&mut self, len: usize) -> io::Result<()> {
+        let data = &self.buf[..len];
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let mut crc = Crc::new();
+        crc.update(data);
+
+        // total block size minus one
+        let block_size = (compressed.len() + 25) as u16;
+        self.writer.write_all(&[
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+            0x02, 0x00,
+        ])?;
+        self.writer.write_all(&block_size.to_le_bytes())?;
+        self.writer.write_all(&compressed)?;
+        self.writer.write_all(&crc.sum().to_le_bytes())?;
+        self.writer.write_all(&(len as u32).to_le_bytes())?;
+
+        self.buf.drain(..len);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+
+        while self.buf.len() >= BGZF_BLOCK_SIZE {
+            self.write_block(BGZF_BLOCK_SIZE)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_block(self.buf.len())?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        let _ = self.writer.write_all(&BGZF_EOF);
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type WriteRecord = fn(
+        &mut (dyn Write + std::marker::Send),
+        &[u8],
+        u16,
+        &[u8],
+        Option<&[u8]>,
+        &[([u8; 2], Data)],
+    ) -> io::Result<()>;
+
+    fn tags() -> Vec<([u8; 2], Data)> {
+        vec![
+            (*b"XA", Data::Bool(true)),
+            (*b"XB", Data::UInt(7)),
+            (*b"XC", Data::UInt(u32::MAX as usize)),
+            (*b"XD", Data::UInt(u32::MAX as usize + 1)),
+            (*b"XE", Data::Int(-7)),
+            (*b"XF", Data::Int(i32::MIN as isize - 1)),
+            (*b"XG", Data::Float(0.25)),
+            (*b"XH", Data::Bytes(b"ACGT".to_vec())),
+        ]
+    }
+
+    // bools are stored as integers and integers that do not fit in 32 bits are stored as strings
+    fn expected_tags() -> Vec<(InlineString, Data)> {
+        [
+            ("XA", Data::UInt(1)),
+            ("XB", Data::UInt(7)),
+            ("XC", Data::UInt(u32::MAX as usize)),
+            ("XD", Data::Bytes(b"4294967296".to_vec())),
+            ("XE", Data::Int(-7)),
+            ("XF", Data::Bytes(b"-2147483649".to_vec())),
+            ("XG", Data::Float(0.25)),
+            ("XH", Data::Bytes(b"ACGT".to_vec())),
+        ]
+        .into_iter()
+        .map(|(tag, data)| (InlineString::new(tag.as_bytes()), data))
+        .collect()
+    }
+
+    fn write_records(writer: &mut (dyn Write + std::marker::Send), write_record: WriteRecord) {
+        let tags = tags();
+        write_record(writer, b"a", 0, b"ACGTN", Some(b"!+5?I"), &tags).unwrap();
+        write_record(writer, b"b", 0, b"ACG", None, &tags).unwrap();
+    }
+
+    fn check_records(reader: &mut dyn SamRecordReader) {
+        let a = reader.next_record().unwrap().unwrap();
+        assert_eq!(a.name, b"a");
+        assert_eq!(a.flag, 0);
+        assert_eq!(a.seq, b"ACGTN");
+        assert_eq!(a.qual.as_deref(), Some(&b"!+5?I"[..]));
+        assert_eq!(a.tags, expected_tags());
+
+        let b = reader.next_record().unwrap().unwrap();
+        assert_eq!(b.name, b"b");
+        assert_eq!(b.seq, b"ACG");
+        assert_eq!(b.qual, None);
+        assert_eq!(b.tags, expected_tags());
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn sam_round_trip() {
+        let mut bytes = Vec::new();
+        write_sam_header(&mut bytes);
+        write_records(&mut bytes, write_sam_record);
+
+        check_records(&mut SamTextReader::new(&bytes[..]));
+    }
+
+    #[test]
+    fn bam_round_trip() {
+        let mut bytes = Vec::new();
+        {
+            let mut writer = BgzfWriter::new(&mut bytes);
+            write_bam_header(&mut writer);
+            write_records(&mut writer, write_bam_record);
+        }
+
+        let mut reader = BamReader::new(BufReader::new(MultiGzDecoder::new(&bytes[..]))).unwrap();
+        check_records(&mut reader);
+    }
+
+    #[test]
+    fn mate_without_qual() {
+        let mut bytes = Vec::new();
+        write_sam_header(&mut bytes);
+        write_sam_record(&mut bytes, b"a", sam_flag(true, 1), b"AC", Some(b"I5"), &[]).unwrap();
+        write_sam_record(&mut bytes, b"a", sam_flag(true, 2), b"GGT", None, &[]).unwrap();
+
+        let reads = iter_sam_bytes(&bytes).unwrap().run_collect_reads().unwrap();
+        let ((_, seq1, qual1), (_, seq2, qual2)) = reads[0].to_fastq2().unwrap();
+        assert_eq!((seq1, qual1), (&b"AC"[..], Some(&b"I5"[..])));
+        assert_eq!((seq2, qual2), (&b"GGT"[..], None));
+    }
+
+    #[test]
+    fn bam_long_qname() {
+        let mut bytes = Vec::new();
+        let qname = vec![b'a'; MAX_QNAME_LEN + 1];
+        assert!(write_bam_record(&mut bytes, &qname, 0, b"A", None, &[]).is_err());
+        assert!(write_bam_record(&mut bytes, &qname[1..], 0, b"A", None, &[]).is_ok());
+    }
+
+    #[test]
+    fn invalid_utf8_tag() {
+        assert!(parse_sam_tag(b"X\xff:i:1").is_err());
+
+        let mut t = &b"X\xffAa"[..];
+        assert!(parse_bam_tag(&mut t).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+/// Placeholder quality score for bases that do not have quality scores.
+pub const UNKNOWN_QUAL: u8 = b'I';
+
+pub fn complement(c: u8) -> u8 {
+    match c {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        // IUPAC ambiguity codes
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        b'r' => b'y',
+        b'y' => b'r',
+        b'k' => b'm',
+        b'm' => b'k',
+        b'b' => b'v',
+        b'v' => b'b',
+        b'd' => b'h',
+        b'h' => b'd',
+        _ => c,
+    }
+}
+
+pub fn revcomp(s: &[u8]) -> Vec<u8> {
+    s.iter().rev().map(|&c| complement(c)).collect()
+}
+
+/// Bit mask of the nucleotides represented by an IUPAC code, or zero if the byte is not one.
+fn iupac_mask(c: u8) -> u8 {
+    const A: u8 = 0b0001;
+    const C: u8 = 0b0010;
+    const G: u8 = 0b0100;
+    const T: u8 = 0b1000;
+
+    match c.to_ascii_uppercase() {
+        b'A' => A,
+        b'C' => C,
+        b'G' => G,
+        b'T' | b'U' => T,
+        b'R' => A | G,
+        b'Y' => C | T,
+        b'S' => C | G,
+        b'W' => A | T,
+        b'K' => G | T,
+        b'M' => A | C,
+        b'B' => C | G | T,
+        b'D' => A | G | T,
+        b'H' => A | C | T,
+        b'V' => A | C | G,
+        b'N' => A | C | G | T,
+        _ => 0,
+    }
+}
+
+/// Check whether two bases match when treating IUPAC codes as degenerate bases.
+///
+/// IUPAC codes match if they have any nucleotide in common, ignoring case. Other bytes must be equal.
+pub fn iupac_eq(a: u8, b: u8) -> bool {
+    match (iupac_mask(a), iupac_mask(b)) {
+        (0, _) | (_, 0) => a == b,
+        (a, b) => a & b != 0,
+    }
+}
+
+/// Replace `N`s with a byte that does not match any IUPAC code.
+pub fn mask_n(s: &[u8]) -> Vec<u8> {
+    s.iter()
+        .map(|&c| if c == b'N' || c == b'n' { b'X' } else { c })
+        .collect()
+}
+
+/// Count the matching bases between two strings of the same length.
+///
+/// Returns `None` if there are fewer than `threshold` matching bases.
+pub fn hamming(a: &[u8], b: &[u8], threshold: usize, iupac: bool) -> Option<usize> {
+    if a.len() != b.len() {
+        return None;
+    }
+
+    if iupac {
+        let matches = a.iter().zip(b).filter(|(&a, &b)| iupac_eq(a, b)).count();
+        return if matches >= threshold {
+            Some(matches)
+        } else {
+            None
+        };
+    }
+
+    let a_ptr = a.as_ptr();
+    let b_ptr = b.as_ptr();
+    let n = a.len();
+    let mut res = 0;
+    let mut i = 0;
+
+    unsafe {
+        while i < (n / 8) * 8 {
+            let a_word = std::ptr::read_unaligned(a_ptr.add(i) as *const u64);
+            let b_word = std::ptr::read_unaligned(b_ptr.add(i) as *const u64);
+
+            let xor = a_word ^ b_word;
+            let or1 = xor | (xor >> 1);
+            let or2 = or1 | (or1 >> 2);
+            let or3 = or2 | (or2 >> 4);
+            let mask = or3 & 0x0101010101010101u64;
+            res += mask.count_ones() as usize;
+
+            i += 8;
+        }
+
+        while i < n {
+            res += (*a_ptr.add(i) != *b_ptr.add(i)) as usize;
+            i += 1;
+        }
+    }
+
+    let matches = n - res;
+
+    if matches >= threshold {
+        Some(matches)
+    } else {
+        None
+    }
+}
+
+/// Compute the edit distance between a pattern and a text with Myers' bit-parallel algorithm.
+///
+/// If `free_start` is true, then the pattern can start anywhere in the text. Otherwise, the
+/// pattern must start at the beginning of the text. The end of the pattern can be anywhere in the
+/// text. Patterns longer than 64 bases are split into multiple blocks.
+///
+/// Returns the min number of edits and the end index of the leftmost match with that many edits.
+pub fn myers(text: &[u8], pattern: &[u8], free_start: bool, iupac: bool) -> (usize, usize) {
+    let mut best = (pattern.len(), 0);
+
+    for (end_idx, score) in myers_scores(text, pattern, free_start, iupac)
+        .into_iter()
+        .enumerate()
+    {
+        if score < best.0 {
+            best = (score, end_idx);
+        }
+    }
+
+    best
+}
+
+/// Compute the edit distance of the pattern for each end index in the text (including 0) with
+/// Myers' bit-parallel algorithm.
+///
+/// See [`myers`] for more details.
+pub fn myers_scores(text: &[u8], pattern: &[u8], free_start: bool, iupac: bool) -> Vec<usize> {
+    let m = pattern.len();
+
+    if m == 0 {
+        return vec![0; text.len() + 1];
+    }
+
+    let blocks = m.div_ceil(64);
+    let last_bit = 1u64 << ((m - 1) % 64);
+
+    // bit vectors of the positions in the pattern that match each byte in the text
+    let mut peq = vec![0u64; 256 * blocks];
+    let mut seen = [false; 256];
+
+    for &c in text {
+        if seen[c as usize] {
+            continue;
+        }
+        seen[c as usize] = true;
+
+        for (i, &p) in pattern.iter().enumerate() {
+            if (iupac && iupac_eq(c, p)) || (!iupac && c == p) {
+                peq[(c as usize) * blocks + i / 64] |= 1u64 << (i % 64);
+            }
+        }
+    }
+
+    // vertical deltas are all +1 in the first column
+    let mut pv = vec![!0u64; blocks];
+    let mut mv = vec![0u64; blocks];
+    // edit distance at the last row of the pattern
+    let mut score = m;
+    let mut scores = Vec::with_capacity(text.len() + 1);
+    scores.push(score);
+
+    for &c in text {
+        // horizontal delta coming into the top of the current block
+        let mut hin: i32 = if free_start { 0 } else { 1 };
+
+        for b in 0..blocks {
+            let p = pv[b];
+            let n = mv[b];
+            let hin_neg = (hin < 0) as u64;
+            let hin_pos = (hin > 0) as u64;
+
+            let eq = peq[(c as usize) * blocks + b];
+            let xv = eq | n;
+            let eq = eq | hin_neg;
+            let xh = ((eq & p).wrapping_add(p) ^ p) | eq;
+            let mut ph = n | !(xh | p);
+            let mut mh = p & xh;
+
+            if b == blocks - 1 {
+                score = score + ((ph & last_bit) != 0) as usize - ((mh & last_bit) != 0) as usize;
+            }
+
+            hin = ((ph >> 63) as i32) - ((mh >> 63) as i32);
+            ph = (ph << 1) | hin_pos;
+            mh = (mh << 1) | hin_neg;
+            pv[b] = mh | !(xv | ph);
+            mv[b] = ph & xv;
+        }
+
+        scores.push(score);
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive_scores(text: &[u8], pattern: &[u8], free_start: bool, iupac: bool) -> Vec<usize> {
+        let eq = |a: u8, b: u8| if iupac { iupac_eq(a, b) } else { a == b };
+        // the first row is the empty pattern
+        let mut prev = (0..=text.len())
+            .map(|j| if free_start { 0 } else { j })
+            .collect::<Vec<_>>();
+
+        for (i, &p) in pattern.iter().enumerate() {
+            let mut curr = vec![i + 1; text.len() + 1];
+
+            for (j, &c) in text.iter().enumerate() {
+                let sub = prev[j] + !eq(c, p) as usize;
+                curr[j + 1] = sub.min(prev[j + 1] + 1).min(curr[j] + 1);
+            }
+
+            prev = curr;
+        }
+
+        prev
+    }
+
+    fn random_seq(state: &mut u64, len: usize, alphabet: &[u8]) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                alphabet[((*state >> 33) as usize) % alphabet.len()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn myers_matches_naive() {
+        let mut state = 1;
+
+        for &len in &[1, 5, 63, 64, 65, 100, 128, 129, 200] {
+            for &(free_start, iupac) in
+                &[(false, false), (true, false), (false, true), (true, true)]
+            {
+                let alphabet: &[u8] = if iupac { b"ACGTNRYacgt" } else { b"ACGT" };
+
+                for _ in 0..10 {
+                    let pattern = random_seq(&mut state, len, alphabet);
+                    // mutate a copy of the pattern so there are close matches
+                    let mut text = random_seq(&mut state, len / 2, alphabet);
+                    text.extend(
+                        pattern
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, &c)| match i % 7 {
+                                0 => None,
+                                3 => Some(b'G'),
+                                _ => Some(c),
+                            }),
+                    );
+                    text.extend(random_seq(&mut state, len / 3, alphabet));
+
+                    let expected = naive_scores(&text, &pattern, free_start, iupac);
+                    assert_eq!(
+                        myers_scores(&text, &pattern, free_start, iupac),
+                        expected,
+                        "pattern length {len}, free_start {free_start}, iupac {iupac}"
+                    );
+
+                    let min = *expected.iter().min().unwrap();
+                    let end_idx = expected.iter().position(|&s| s == min).unwrap();
+                    assert_eq!(myers(&text, &pattern, free_start, iupac), (min, end_idx));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn myers_edge_cases() {
+        assert_eq!(myers_scores(b"ACGT", b"", false, false), vec![0; 5]);
+        assert_eq!(myers_scores(b"", b"ACGT", true, false), vec![4]);
+        assert_eq!(myers(b"TTACGTT", b"ACGT", true, false), (0, 6));
+        assert_eq!(myers(b"TTACGTT", b"ACGT", false, false), (2, 6));
+        assert_eq!(myers(b"ACGT", b"ANGT", false, true), (0, 4));
+        assert_eq!(myers(b"ACGT", b"ANGT", false, false), (1, 4));
+    }
+}